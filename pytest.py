@@ -4,7 +4,7 @@ import rlviser_py as vis
 import RocketSim as rs
 
 
-def run(arena, game_mode):
+def run(arena):
     # Setup example arena
     car = arena.add_car(rs.Team.BLUE)
     car.set_state(rs.CarState(pos=rs.Vec(z=17), vel=rs.Vec(x=50), boost=100))
@@ -20,14 +20,7 @@ def run(arena, game_mode):
         arena.step(1)
 
         # Render the current game state
-        pad_states = [pad.get_state().is_active for pad in arena.get_boost_pads()]
-        ball = arena.ball.get_state()
-        car_data = [
-            (car.id, car.team, car.get_config(), car.get_state())
-            for car in arena.get_cars()
-        ]
-
-        vis.render(steps, arena.tick_rate, game_mode, pad_states, ball, car_data)
+        vis.render_arena(arena)
 
         # sleep to simulate running real time (it will run a LOT after otherwise)
         time.sleep(max(0, start_time + steps / arena.tick_rate - time.time()))
@@ -40,11 +33,7 @@ if __name__ == "__main__":
     # Create example arena
     arena = rs.Arena(game_mode)
 
-    # Set boost pad locations
-    vis.set_boost_pad_locations(
-        [pad.get_pos().as_tuple() for pad in arena.get_boost_pads()]
-    )
-    run(arena, game_mode)
+    run(arena)
 
     # Tell RLViser to exit
     print("Closing...")
@@ -53,7 +42,7 @@ if __name__ == "__main__":
 
     print("Re-opening then running...")
     vis.launch()
-    run(arena, game_mode)
+    run(arena)
 
     print("Exiting...")
     vis.quit()
@@ -1,6 +1,9 @@
-from typing import Sequence
+from os import PathLike
+from typing import Any, Callable, Literal, Mapping, Sequence, overload
 
-from RocketSim import BallState, CarState, GameMode, CarConfig
+import numpy as np
+from numpy.typing import NDArray
+from RocketSim import Arena, BallState, BoostPadState, CarState, GameMode, CarConfig
 
 TVec3 = tuple[float, float, float]
 """
@@ -18,15 +21,131 @@ TCar = tuple[int, TVec3, TRotmat, TVec3, TVec3, float, bool, bool, bool, float]
 """
 The items are (car_id, location, rotation, velocity, angular velocity, boost, has jumped, has double jumped, has flipped, demo respawn timer) respectively
 """
+TTile = tuple[TVec3, int]
+"""
+The items are (location, state) respectively, where the state is 0 for full, 1 for damaged and 2 for broken
+"""
+TTiles = tuple[Sequence[TTile], Sequence[TTile]]
+"""
+The Dropshot tiles of the blue and orange sides respectively
+"""
+TValidation = Literal["off", "warn", "raise", "repair"]
+"""
+What `render()` does with game states that fail its sanity checks
+"""
 
-def set_boost_pad_locations(locations: Sequence[TVec3]) -> ...:
-    pass
+class RLViserError(Exception):
+    """
+    Base class for all of the errors raised by rlviser_py
+    """
+
+class BindError(RLViserError):
+    """
+    The socket used to talk to RLViser couldn't be set up
+    """
+
+class SendError(RLViserError):
+    """
+    A message couldn't be sent to RLViser
+    """
+
+class LaunchError(RLViserError):
+    """
+    The RLViser executable couldn't be started
+    """
+
+class ProtocolError(RLViserError):
+    """
+    A message couldn't be encoded or decoded
+    """
+
+class Viewer:
+    """
+    A connection to a single instance of RLViser
+
+    Each viewer has its own socket, boost pad locations and game speed/pause/state set requests.
+    The module-level functions all use a default viewer that's shared by the whole process,
+    and each method here works the same as the module-level function of the same name.
+
+    Any address or port that isn't given is read from the `RLVISER_BIND_ADDR`, `RLVISER_LOCAL_PORT`,
+    `RLVISER_HOST` and `RLVISER_PORT` environment variables, falling back to `0.0.0.0:34254` locally
+    and RLViser at `127.0.0.1:45243`. A `local_port` of 0 lets the OS pick a free port.
+
+    The RLViser executable is `rlviser_path` or the `RLVISER_PATH` environment variable if either is set,
+    otherwise the first `rlviser` found in the working directory, the `rlviser_py` package directory or `PATH`.
+    It's started with `rlviser_args` and with `rlviser_env` added to the inherited environment.
+    Its stdout and stderr are forwarded line by line to the `rlviser` logger at the INFO and WARNING levels,
+    or appended to `rlviser_log_file` if it's set.
+    """
+
+    def __init__(self, bind_addr: str | None = None, local_port: int | None = None, rlviser_host: str | None = None, rlviser_port: int | None = None, rlviser_path: str | PathLike[str] | None = None, rlviser_args: Sequence[str] | None = None, rlviser_env: Mapping[str, str] | None = None, rlviser_log_file: str | PathLike[str] | None = None) -> None: ...
+    def set_boost_pad_locations(self, locations: Sequence[TVec3 | tuple[TVec3, bool]] | None) -> ...: ...
+    def set_validation(self, mode: TValidation) -> ...: ...
+    @overload
+    def get_state_set(self, include_tiles: Literal[False] = False, full: Literal[False] = False) -> tuple[Sequence[float], TBall, Sequence[TCar]] | None: ...
+    @overload
+    def get_state_set(self, include_tiles: Literal[True], full: Literal[False] = False) -> tuple[Sequence[float], TBall, Sequence[TCar], TTiles | None] | None: ...
+    @overload
+    def get_state_set(self, include_tiles: bool = False, *, full: Literal[True]) -> dict[str, Any] | None: ...
+    def get_game_speed(self) -> float: ...
+    def get_game_paused(self) -> bool: ...
+    def report_game_speed(self, speed: float) -> ...: ...
+    def report_game_paused(self, paused: bool) -> ...: ...
+    def is_connected(self) -> bool: ...
+    def last_seen_seconds(self) -> float | None: ...
+    def on_connection_change(self, callback: Callable[[bool], None] | None) -> ...: ...
+    def render(self, tick_count: int, tick_rate: float, game_mode: GameMode, boost_pad_states: Sequence[bool | TBoostPadState | BoostPadState], ball: BallState, cars: Sequence[CarData], tiles: TTiles | None = None, validation: TValidation | None = None) -> ...: ...
+    def render_arrays(self, tick_count: int, tick_rate: float, game_mode: GameMode, boost_pad_states: Sequence[bool | TBoostPadState | BoostPadState], ball: BallState, car_ids: Sequence[int] | NDArray[np.integer], car_teams: Sequence[int] | NDArray[np.integer], car_positions: NDArray[np.float32], car_rotations: NDArray[np.float32], car_velocities: NDArray[np.float32], car_ang_velocities: NDArray[np.float32], car_boost: NDArray[np.float32], car_flags: NDArray[np.bool_], car_configs: Sequence[CarConfig] | None = None, tiles: TTiles | None = None, validation: TValidation | None = None) -> ...: ...
+    def render_arena(self, arena: Arena, tiles: TTiles | None = None, validation: TValidation | None = None) -> ...: ...
+    def add_render(self, group_id: int, commands: Sequence[RenderCommand], lifetime_seconds: float | None = None, lifetime_ticks: int | None = None) -> ...: ...
+    def remove_render(self, group_id: int) -> ...: ...
+    def clear_renders(self) -> ...: ...
+    def launch(self, bind_addr: str | None = None, local_port: int | None = None, rlviser_host: str | None = None, rlviser_port: int | None = None, rlviser_path: str | PathLike[str] | None = None, rlviser_args: Sequence[str] | None = None, rlviser_env: Mapping[str, str] | None = None, rlviser_log_file: str | PathLike[str] | None = None) -> ...: ...
+    def is_rlviser_running(self) -> bool: ...
+    def rlviser_pid(self) -> int | None: ...
+    def rlviser_exit_code(self) -> int | None: ...
+    def wait_for_rlviser(self, timeout: float | None = None) -> int | None: ...
+    def quit(self, timeout: float | None = None, force: bool = False) -> bool: ...
+
+def set_boost_pad_locations(locations: Sequence[TVec3 | tuple[TVec3, bool]] | None) -> ...:
+    """
+    Sets the boost pads to send to RLViser, in the same order as the states given to `render()`.
+
+    Each pad is its location and whether it's big, or just its location, in which case it's big if it's at z=73 like in Soccar.
+    With None, the standard layout of the game mode being rendered is used, which is the default.
+    Soccar, Hoops and Snowday have built-in layouts, sorted by y and then x like RocketSim's `Arena.get_boost_pads()`.
+    """
+
+def set_validation(mode: TValidation) -> ...:
+    """
+    Sets what `render()` does with game states that fail its sanity checks:
+    non-finite positions, velocities or rotations, rotations that aren't orthonormal,
+    the ball or a car outside of the arena of the game mode, or boost outside of 0 to 100.
+
+    "off" - No checks, the default
+    "warn" - Logs a warning under the `rlviser_py` logger and sends the state as-is
+    "raise" - Raises `ValueError` and doesn't send the state
+    "repair" - Zeroes non-finite values, orthonormalizes rotations and clamps positions and boost before sending
+    """
 
-def get_state_set() -> tuple[Sequence[float], TBall, Sequence[TCar]] | None:
+@overload
+def get_state_set(include_tiles: Literal[False] = False, full: Literal[False] = False) -> tuple[Sequence[float], TBall, Sequence[TCar]] | None: ...
+@overload
+def get_state_set(include_tiles: Literal[True], full: Literal[False] = False) -> tuple[Sequence[float], TBall, Sequence[TCar], TTiles | None] | None: ...
+@overload
+def get_state_set(include_tiles: bool = False, *, full: Literal[True]) -> dict[str, Any] | None: ...
+def get_state_set(include_tiles: bool = False, full: bool = False) -> tuple[Sequence[float], TBall, Sequence[TCar]] | tuple[Sequence[float], TBall, Sequence[TCar], TTiles | None] | dict[str, Any] | None:
     """
     Sequence[float] - Boost pad states, 0 for full and some positive value for the time in seconds until it respawns
     TBall - Ball state
     Sequence[TCar] - Car states
+    TTiles | None - Dropshot tile states, only included if `include_tiles` is set, and None outside of Dropshot
+
+    If `full` is set, the whole game state is returned as a dict instead, with the keys
+    `tick_count`, `tick_rate`, `game_mode`, `ball`, `cars`, `pads` and `tiles`.
+    The ball and each car's `state` and `config` are dicts with every field, named after the
+    `BallState`, `CarState` and `CarConfig` attributes they were read from, and each car also has its `id` and `team`.
+    Vectors are `[x, y, z]` lists and rotation matrices are `[forward, right, up]`.
     """
     pass
 
@@ -61,18 +180,233 @@ def report_game_paused(paused: bool) -> ...:
     """
     pass
 
+def is_connected() -> bool:
+    """
+    Returns whether RLViser has responded in the last few seconds.
+
+    Checking this also connects to RLViser (without launching it) and keeps a heartbeat going,
+    so it can be used to skip rendering when nobody is watching. The first render still launches it if needed.
+    The first call only sends the handshake, so it returns False until RLViser has had time to answer.
+    """
+
+def last_seen_seconds() -> float | None:
+    """
+    Returns the number of seconds since anything was received from RLViser, or None if nothing has been.
+
+    Like `is_connected()`, this connects to RLViser without launching it if nothing else has yet.
+    """
+
+def on_connection_change(callback: Callable[[bool], None] | None) -> ...:
+    """
+    Sets a function that's called with True when RLViser connects and False when it's lost, or removes it if None.
+
+    The connection is only checked while this module is being used, like when calling `render()` or `is_connected()`.
+    """
+
+TBoostPadState = tuple[bool, float, int, int]
+"""
+The items are (is_active, cooldown, cur_locked_car_id, prev_locked_car_id) respectively
+"""
+
 CarData = tuple[int, int, CarConfig, CarState]
 """
-The items are (car_id, team, car_config, car_state) respectively
+The items are (car_id, team, car_config, car_state) respectively, where car_id can be any non-zero 64-bit unsigned integer
 """
 
-def render(tick_count: int, tick_rate: float, game_mode: GameMode, boost_pad_states: Sequence[bool], ball: BallState, cars: Sequence[CarData]) -> ...:
-    pass
+def render(tick_count: int, tick_rate: float, game_mode: GameMode, boost_pad_states: Sequence[bool | TBoostPadState | BoostPadState], ball: BallState, cars: Sequence[CarData], tiles: TTiles | None = None, validation: TValidation | None = None) -> ...:
+    """
+    Sends the game state to RLViser. In Dropshot, pass the state of every tile as `tiles`.
+
+    Each boost pad state can be just whether it's active, or its full state as a tuple or a `BoostPadState`
+    so RLViser can show the time until it respawns.
+
+    Raises `ValueError` if the game mode or a car's team is out of range, if a car id is 0 or used by more than one car,
+    or if the number of boost pad states doesn't match the boost pad locations (set or the game mode's standard layout).
+
+    The state is checked according to `validation`, or the mode given to `set_validation()` if it's None.
+    """
+
+def render_arrays(tick_count: int, tick_rate: float, game_mode: GameMode, boost_pad_states: Sequence[bool | TBoostPadState | BoostPadState], ball: BallState, car_ids: Sequence[int] | NDArray[np.integer], car_teams: Sequence[int] | NDArray[np.integer], car_positions: NDArray[np.float32], car_rotations: NDArray[np.float32], car_velocities: NDArray[np.float32], car_ang_velocities: NDArray[np.float32], car_boost: NDArray[np.float32], car_flags: NDArray[np.bool_], car_configs: Sequence[CarConfig] | None = None, tiles: TTiles | None = None, validation: TValidation | None = None) -> ...:
+    """
+    Like `render()`, but with the cars given as NumPy arrays with one row per car,
+    which is much faster than reading every attribute of a `CarState` for each car.
+
+    car_ids - (n,) uint64
+    car_teams - (n,) uint8
+    car_positions - (n, 3) float32
+    car_rotations - (n, 3, 3) float32, with the rows being forward, right and up
+    car_velocities - (n, 3) float32
+    car_ang_velocities - (n, 3) float32
+    car_boost - (n,) float32
+    car_flags - (n, 7) bool, with the columns being
+    is_on_ground, has_jumped, has_double_jumped, has_flipped, is_supersonic, is_boosting and is_demoed
+
+    Arrays of other dtypes (like NumPy's default float64) and sequences are converted to these first,
+    which copies them, so passing the listed dtypes is fastest.
+    The other fields of each car's state are left at their defaults.
+    `car_configs` can be left out for cars that were in the last state sent, which reuse their config from it.
+
+    Raises `ValueError` if an array has the wrong shape, or if a car's config isn't given the first time it's rendered.
+    """
+
+def render_arena(arena: Arena, tiles: TTiles | None = None, validation: TValidation | None = None) -> ...:
+    """
+    Sends the current state of a RocketSim arena to RLViser,
+    with its tick count, tick rate, game mode, boost pads, ball and cars.
+
+    If no boost pad locations have been set, they're set from the arena's pads on first use.
+    """
+
+TVec2 = tuple[float, float]
+"""
+The items are (X, Y) respectively, in screen space
+"""
+TColor = tuple[float, float, float, float]
+"""
+The items are (R, G, B, A) respectively, each from 0 to 1
+"""
+
+class Anchor:
+    """
+    What the coordinates of a world space render command are relative to
+    """
+
+    class World(Anchor):
+        """
+        Absolute world space, the default
+        """
+
+        def __init__(self) -> None: ...
+
+    class Car(Anchor):
+        """
+        Offsets in the local (forward, right, up) frame of the car with the given id,
+        so the render follows the car's position and rotation without being resent
+        """
+
+        id: int
+        def __init__(self, id: int) -> None: ...
+
+    class Ball(Anchor):
+        """
+        Offsets from the position of the ball, without rotation
+        """
+
+        def __init__(self) -> None: ...
+
+class RenderCommand:
+    """
+    A single debug render primitive, created by one of the builder functions below
+    """
+
+def line_2d(start: TVec2, end: TVec2, color: TColor) -> RenderCommand:
+    """
+    A line between two points in screen space
+    """
+
+def line_3d(start: TVec3, end: TVec3, color: TColor, anchor: Anchor | None = None) -> RenderCommand:
+    """
+    A line between two points in world space
+    """
+
+def line_strip(positions: Sequence[TVec3], color: TColor, anchor: Anchor | None = None) -> RenderCommand:
+    """
+    Connected line segments through each of the points in world space
+    """
+
+def text_2d(text: str, position: TVec2, color: TColor, scale: float = 1.0, background: TColor | None = None) -> RenderCommand:
+    """
+    Text in screen space, anchored at its top-left corner
+    """
+
+def text_3d(text: str, position: TVec3, color: TColor, scale: float = 1.0, background: TColor | None = None, anchor: Anchor | None = None) -> RenderCommand:
+    """
+    Text centered on a point in world space, always facing the camera
+    """
+
+def sphere(center: TVec3, radius: float, color: TColor, filled: bool = False, anchor: Anchor | None = None) -> RenderCommand:
+    """
+    A sphere centered on a point in world space
+    """
+
+def oriented_box(center: TVec3, rot_mat: TRotmat, size: TVec3, color: TColor, filled: bool = False, anchor: Anchor | None = None) -> RenderCommand:
+    """
+    A box centered on a point in world space, rotated by `rot_mat`
+
+    `size` is the full length of the box along each of the (forward, right, up) axes
+    """
 
-def launch() -> ...:
+def cylinder(start: TVec3, end: TVec3, radius: float, color: TColor, filled: bool = False, anchor: Anchor | None = None) -> RenderCommand:
+    """
+    A cylinder between the centers of its two circular faces in world space
+    """
+
+def arrow(start: TVec3, end: TVec3, color: TColor, head_size: float = 20.0, filled: bool = False, anchor: Anchor | None = None) -> RenderCommand:
+    """
+    An arrow pointing from `start` to `end` in world space, `head_size` is the length of the arrow head
+    """
+
+def add_render(group_id: int, commands: Sequence[RenderCommand], lifetime_seconds: float | None = None, lifetime_ticks: int | None = None) -> ...:
+    """
+    Adds a group of debug render commands to RLViser, replacing any existing group with the same id
+
+    By default the group stays until it's removed, but RLViser can remove it by itself after
+    either `lifetime_seconds` real-time seconds or `lifetime_ticks` game ticks (only one can be set).
+    Raises `ValueError` if `lifetime_seconds` isn't a finite, positive number.
+    """
+
+def remove_render(group_id: int) -> ...:
+    """
+    Removes a group of debug render commands from RLViser
+    """
+
+def clear_renders() -> ...:
+    """
+    Removes every group of debug render commands added by this process, also done by `quit()`
+    """
+
+def launch(bind_addr: str | None = None, local_port: int | None = None, rlviser_host: str | None = None, rlviser_port: int | None = None, rlviser_path: str | PathLike[str] | None = None, rlviser_args: Sequence[str] | None = None, rlviser_env: Mapping[str, str] | None = None, rlviser_log_file: str | PathLike[str] | None = None) -> ...:
     """
     Opens RLViser. Useful if `quit()` was called and you want to open it again.
+
+    Any of the addresses or ports that are given replace the ones used to connect to RLViser,
+    and any of the executable path, arguments, environment variables or log file replace the ones RLViser is started with.
+    See `Viewer` for their defaults. Nothing is started if the RLViser launched before is still running.
     """
 
-def quit() -> ...:
-    pass
+def is_rlviser_running() -> bool:
+    """
+    Whether the RLViser launched by this process is still running
+    """
+
+def rlviser_pid() -> int | None:
+    """
+    The process id of the RLViser launched by this process, or `None` if it hasn't been launched
+    """
+
+def rlviser_exit_code() -> int | None:
+    """
+    The exit code of the RLViser launched by this process,
+    or `None` if it's still running, hasn't been launched or was killed by a signal
+    """
+
+def wait_for_rlviser(timeout: float | None = None) -> int | None:
+    """
+    Waits for the RLViser launched by this process to exit and returns its exit code,
+    or `None` if `timeout` seconds pass first
+    """
+
+def quit(timeout: float | None = None, force: bool = False) -> bool:
+    """
+    Tells RLViser to exit and removes the render groups added by this process.
+
+    With a `timeout`, waits up to that many seconds for the RLViser launched by this process to exit,
+    sending the quit signal again in case it was lost.
+    If `force` is set, it's killed if it's still running after the timeout, which defaults to a second.
+    Returns whether the RLViser launched by this process (if any) is no longer running.
+
+    The RLViser launched by this process is also closed like this when the interpreter exits,
+    and the one launched by a `Viewer` when the viewer is garbage collected.
+
+    The render groups and last game state are sent again automatically if RLViser is launched again or restarts.
+    """
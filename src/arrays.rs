@@ -0,0 +1,195 @@
+use numpy::{
+    Element, Ix1, Ix2, Ix3, PyReadonlyArray, PyUntypedArrayMethods,
+    ndarray::{ArrayView2, Dimension},
+};
+use pyo3::{
+    exceptions::{PyTypeError, PyValueError},
+    prelude::*,
+    sync::PyOnceLock,
+};
+
+use crate::{
+    bytes::{CarConfig, CarState, GameState, RotMat, Vec3},
+    viewer::Car,
+};
+
+/// The columns of the `car_flags` array given to `render_arrays`, in order
+const CAR_FLAGS: [&str; 7] = [
+    "is_on_ground",
+    "has_jumped",
+    "has_double_jumped",
+    "has_flipped",
+    "is_supersonic",
+    "is_boosting",
+    "is_demoed",
+];
+
+static NUMPY_IMPORTED: PyOnceLock<()> = PyOnceLock::new();
+
+/// A read-only NumPy array argument
+///
+/// The `numpy` crate panics when checking an argument's type if NumPy isn't installed,
+/// so this imports it first to raise an `ImportError` instead.
+/// Arrays of another dtype (like NumPy's default of float64) and sequences are converted with `numpy.asarray`
+pub struct NdArray<'py, T: Element, D: Dimension>(PyReadonlyArray<'py, T, D>);
+
+pub type Array1<'py, T> = NdArray<'py, T, Ix1>;
+pub type Array2<'py, T> = NdArray<'py, T, Ix2>;
+pub type Array3<'py, T> = NdArray<'py, T, Ix3>;
+
+impl<'py, T: Element, D: Dimension> FromPyObject<'_, 'py> for NdArray<'py, T, D> {
+    type Error = PyErr;
+
+    fn extract(obj: Borrowed<'_, 'py, PyAny>) -> PyResult<Self> {
+        let py = obj.py();
+        NUMPY_IMPORTED.get_or_try_init(py, || py.import("numpy").map(drop))?;
+
+        if let Ok(array) = obj.extract() {
+            return Ok(Self(array));
+        }
+
+        let dtype = T::get_dtype(py);
+        let array = py
+            .import("numpy")?
+            .call_method1("asarray", (obj, &dtype))
+            .map_err(|e| {
+                // a `TypeError` so it's raised with the name of the argument
+                let error =
+                    PyTypeError::new_err(format!("expected an array of {dtype}, {}", e.value(py)));
+                error.set_cause(py, Some(e));
+                error
+            })?;
+
+        array.extract().map(Self).map_err(Into::into)
+    }
+}
+
+/// Check that an array has one row per car, and that each row has the expected shape
+fn check_shape(name: &str, shape: &[usize], num_cars: usize, row_shape: &[usize]) -> PyResult<()> {
+    if shape.first() == Some(&num_cars) && shape[1..] == *row_shape {
+        return Ok(());
+    }
+
+    let expected = [&[num_cars], row_shape].concat();
+    Err(PyValueError::new_err(format!(
+        "Invalid shape {shape:?} for {name}, expected {expected:?} for {num_cars} cars"
+    )))
+}
+
+/// The cars given to `render_arrays`, as one row per car in each array
+pub struct CarArrays<'py> {
+    /// `(n,)` ids
+    pub ids: Array1<'py, u64>,
+    /// `(n,)` teams
+    pub teams: Array1<'py, u8>,
+    /// `(n, 3)` positions
+    pub positions: Array2<'py, f32>,
+    /// `(n, 3, 3)` rotation matrices, with the rows being forward, right and up
+    pub rotations: Array3<'py, f32>,
+    /// `(n, 3)` velocities
+    pub velocities: Array2<'py, f32>,
+    /// `(n, 3)` angular velocities
+    pub ang_velocities: Array2<'py, f32>,
+    /// `(n,)` boost amounts
+    pub boost: Array1<'py, f32>,
+    /// `(n, 7)` flags, with the columns in the order of `CAR_FLAGS`
+    pub flags: Array2<'py, bool>,
+}
+
+impl CarArrays<'_> {
+    /// Convert the arrays to the cars `render` takes
+    ///
+    /// Cars without a config in `configs` reuse their config from `previous`,
+    /// the last game state that was sent
+    pub fn into_cars(
+        self,
+        configs: Option<Vec<CarConfig>>,
+        previous: Option<&GameState>,
+    ) -> PyResult<Vec<Car>> {
+        let num_cars = self.ids.0.len();
+
+        if let Some(configs) = &configs
+            && configs.len() != num_cars
+        {
+            return Err(PyValueError::new_err(format!(
+                "Invalid length {} for car_configs, expected {num_cars} for {num_cars} cars",
+                configs.len()
+            )));
+        }
+
+        check_shape("car_teams", self.teams.0.shape(), num_cars, &[])?;
+        check_shape("car_positions", self.positions.0.shape(), num_cars, &[3])?;
+        check_shape("car_rotations", self.rotations.0.shape(), num_cars, &[3, 3])?;
+        check_shape("car_velocities", self.velocities.0.shape(), num_cars, &[3])?;
+        check_shape(
+            "car_ang_velocities",
+            self.ang_velocities.0.shape(),
+            num_cars,
+            &[3],
+        )?;
+        check_shape("car_boost", self.boost.0.shape(), num_cars, &[])?;
+        check_shape(
+            "car_flags",
+            self.flags.0.shape(),
+            num_cars,
+            &[CAR_FLAGS.len()],
+        )?;
+
+        let ids = self.ids.0.as_array();
+        let teams = self.teams.0.as_array();
+        let positions = self.positions.0.as_array();
+        let rotations = self.rotations.0.as_array();
+        let velocities = self.velocities.0.as_array();
+        let ang_velocities = self.ang_velocities.0.as_array();
+        let boost = self.boost.0.as_array();
+        let flags = self.flags.0.as_array();
+
+        let vec3 = |array: &ArrayView2<f32>, i: usize| {
+            Vec3::new(array[[i, 0]], array[[i, 1]], array[[i, 2]])
+        };
+        let row = |i: usize, j: usize| {
+            Vec3::new(
+                rotations[[i, j, 0]],
+                rotations[[i, j, 1]],
+                rotations[[i, j, 2]],
+            )
+        };
+
+        ids.iter()
+            .copied()
+            .zip(teams.iter().copied())
+            .enumerate()
+            .map(|(i, (id, team))| {
+                let config = match &configs {
+                    Some(configs) => configs[i],
+                    None => previous
+                        .and_then(|state| state.cars.iter().find(|car| car.id == id))
+                        .map(|car| car.config)
+                        .ok_or_else(|| {
+                            PyValueError::new_err(format!(
+                                "No config for car {i} (id {id}), car_configs must be given the first time a car is rendered"
+                            ))
+                        })?,
+                };
+
+                let state = CarState {
+                    pos: vec3(&positions, i),
+                    rot_mat: RotMat::new(row(i, 0), row(i, 1), row(i, 2)),
+                    vel: vec3(&velocities, i),
+                    ang_vel: vec3(&ang_velocities, i),
+                    boost: boost[i],
+                    is_on_ground: flags[[i, 0]],
+                    has_jumped: flags[[i, 1]],
+                    has_double_jumped: flags[[i, 2]],
+                    has_flipped: flags[[i, 3]],
+                    is_supersonic: flags[[i, 4]],
+                    is_boosting: flags[[i, 5]],
+                    is_demoed: flags[[i, 6]],
+                    ..CarState::default()
+                };
+
+                Ok((id, team, config, state))
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,114 @@
+use pyo3::FromPyObject;
+
+use crate::bytes::{GameMode, TVec3, Vec3};
+
+/// Where a boost pad is and whether it's a big one
+#[derive(Clone, Copy, Debug)]
+pub struct BoostPadConfig {
+    pub position: Vec3,
+    pub is_big: bool,
+}
+
+impl BoostPadConfig {
+    const fn new(x: f32, y: f32, z: f32, is_big: bool) -> Self {
+        Self {
+            position: Vec3::new(x, y, z),
+            is_big,
+        }
+    }
+}
+
+/// A boost pad as given to `set_boost_pad_locations`: its location and size,
+/// or just its location, in which case it's big if it's at the height of big pads in Soccar
+#[derive(Clone, Copy, Debug, FromPyObject)]
+pub enum BoostPadLocation {
+    WithSize(TVec3, bool),
+    Position(TVec3),
+}
+
+impl From<BoostPadLocation> for BoostPadConfig {
+    fn from(value: BoostPadLocation) -> Self {
+        match value {
+            BoostPadLocation::WithSize(position, is_big) => Self {
+                position: Vec3::from_array(position),
+                is_big,
+            },
+            BoostPadLocation::Position(position) => Self {
+                position: Vec3::from_array(position),
+                is_big: (position[2] - 73.).abs() < f32::EPSILON,
+            },
+        }
+    }
+}
+
+/// The boost pads of a game mode's standard arena, or none if it doesn't have any
+///
+/// Pads are sorted by y and then x, the same order as RocketSim's `Arena.get_boost_pads()`
+pub const fn default_layout(game_mode: GameMode) -> &'static [BoostPadConfig] {
+    match game_mode {
+        GameMode::Soccar | GameMode::Snowday => &SOCCAR_PADS,
+        GameMode::Hoops => &HOOPS_PADS,
+        GameMode::HeatSeeker | GameMode::Dropshot | GameMode::TheVoid => &[],
+    }
+}
+
+/// Also used in Snowday, which is played in the Soccar arena
+const SOCCAR_PADS: [BoostPadConfig; 34] = [
+    BoostPadConfig::new(0., -4240., 70., false),
+    BoostPadConfig::new(-1792., -4184., 70., false),
+    BoostPadConfig::new(1792., -4184., 70., false),
+    BoostPadConfig::new(-3072., -4096., 73., true),
+    BoostPadConfig::new(3072., -4096., 73., true),
+    BoostPadConfig::new(-940., -3308., 70., false),
+    BoostPadConfig::new(940., -3308., 70., false),
+    BoostPadConfig::new(0., -2816., 70., false),
+    BoostPadConfig::new(-3584., -2484., 70., false),
+    BoostPadConfig::new(3584., -2484., 70., false),
+    BoostPadConfig::new(-1788., -2300., 70., false),
+    BoostPadConfig::new(1788., -2300., 70., false),
+    BoostPadConfig::new(-2048., -1036., 70., false),
+    BoostPadConfig::new(2048., -1036., 70., false),
+    BoostPadConfig::new(0., -1024., 70., false),
+    BoostPadConfig::new(-3584., 0., 73., true),
+    BoostPadConfig::new(-1024., 0., 70., false),
+    BoostPadConfig::new(1024., 0., 70., false),
+    BoostPadConfig::new(3584., 0., 73., true),
+    BoostPadConfig::new(0., 1024., 70., false),
+    BoostPadConfig::new(-2048., 1036., 70., false),
+    BoostPadConfig::new(2048., 1036., 70., false),
+    BoostPadConfig::new(-1788., 2300., 70., false),
+    BoostPadConfig::new(1788., 2300., 70., false),
+    BoostPadConfig::new(-3584., 2484., 70., false),
+    BoostPadConfig::new(3584., 2484., 70., false),
+    BoostPadConfig::new(0., 2816., 70., false),
+    BoostPadConfig::new(940., 3308., 70., false),
+    BoostPadConfig::new(-940., 3310., 70., false),
+    BoostPadConfig::new(-3072., 4096., 73., true),
+    BoostPadConfig::new(3072., 4096., 73., true),
+    BoostPadConfig::new(-1792., 4184., 70., false),
+    BoostPadConfig::new(1792., 4184., 70., false),
+    BoostPadConfig::new(0., 4240., 70., false),
+];
+
+const HOOPS_PADS: [BoostPadConfig; 20] = [
+    BoostPadConfig::new(-2176., -2944., 72., true),
+    BoostPadConfig::new(2176., -2944., 72., true),
+    BoostPadConfig::new(0., -2816., 64., false),
+    BoostPadConfig::new(-1280., -2304., 64., false),
+    BoostPadConfig::new(1280., -2304., 64., false),
+    BoostPadConfig::new(-1536., -1024., 64., false),
+    BoostPadConfig::new(1536., -1024., 64., false),
+    BoostPadConfig::new(-512., -512., 64., false),
+    BoostPadConfig::new(512., -512., 64., false),
+    BoostPadConfig::new(-2432., 0., 72., true),
+    BoostPadConfig::new(2432., 0., 72., true),
+    BoostPadConfig::new(-512., 512., 64., false),
+    BoostPadConfig::new(512., 512., 64., false),
+    BoostPadConfig::new(-1536., 1024., 64., false),
+    BoostPadConfig::new(1536., 1024., 64., false),
+    BoostPadConfig::new(-1280., 2304., 64., false),
+    BoostPadConfig::new(1280., 2304., 64., false),
+    BoostPadConfig::new(0., 2816., 64., false),
+    BoostPadConfig::new(-2176., 2944., 72., true),
+    BoostPadConfig::new(2176., 2944., 72., true),
+];
@@ -1,4 +1,4 @@
-use pyo3::FromPyObject;
+use pyo3::{FromPyObject, prelude::*, types::PyDict};
 
 use crate::flat::rocketsim as fb;
 
@@ -15,6 +15,28 @@ pub trait FromFlat<T> {
     fn from_flat(flat: T) -> Self;
 }
 
+// ---------------------------------------------------------------------------
+// Trait for converting our types into Python dicts, with a key for every field
+// named after the attribute it's read from. Vectors become `[x, y, z]` and
+// rotation matrices `[forward, right, up]`, same as in the tuples.
+// ---------------------------------------------------------------------------
+
+pub trait ToDict {
+    fn to_dict<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>>;
+}
+
+fn to_dicts<'py, T: ToDict>(py: Python<'py>, items: &[T]) -> PyResult<Vec<Bound<'py, PyDict>>> {
+    items.iter().map(|item| item.to_dict(py)).collect()
+}
+
+macro_rules! py_dict {
+    ($py:expr, { $($key:literal: $value:expr),* $(,)? }) => {{
+        let dict = PyDict::new($py);
+        $(dict.set_item($key, $value)?;)*
+        Ok(dict)
+    }};
+}
+
 // ---------------------------------------------------------------------------
 // GameMode
 // ---------------------------------------------------------------------------
@@ -32,14 +54,15 @@ pub enum GameMode {
 }
 
 impl GameMode {
-    pub const fn from_u8(value: u8) -> Self {
+    pub const fn from_u8(value: u8) -> Option<Self> {
         match value {
-            0 => Self::Soccar,
-            1 => Self::Hoops,
-            2 => Self::HeatSeeker,
-            3 => Self::Snowday,
-            4 => Self::Dropshot,
-            _ => Self::TheVoid,
+            0 => Some(Self::Soccar),
+            1 => Some(Self::Hoops),
+            2 => Some(Self::HeatSeeker),
+            3 => Some(Self::Snowday),
+            4 => Some(Self::Dropshot),
+            5 => Some(Self::TheVoid),
+            _ => None,
         }
     }
 }
@@ -102,6 +125,34 @@ impl Vec3 {
     pub const fn to_array(self) -> TVec3 {
         [self.x, self.y, self.z]
     }
+
+    pub const fn is_finite(self) -> bool {
+        self.x.is_finite() && self.y.is_finite() && self.z.is_finite()
+    }
+
+    pub const fn dot(self, other: Self) -> f32 {
+        self.x * other.x + self.y * other.y + self.z * other.z
+    }
+
+    pub const fn cross(self, other: Self) -> Self {
+        Self::new(
+            self.y * other.z - self.z * other.y,
+            self.z * other.x - self.x * other.z,
+            self.x * other.y - self.y * other.x,
+        )
+    }
+
+    pub const fn scale(self, factor: f32) -> Self {
+        Self::new(self.x * factor, self.y * factor, self.z * factor)
+    }
+
+    pub const fn sub(self, other: Self) -> Self {
+        Self::new(self.x - other.x, self.y - other.y, self.z - other.z)
+    }
+
+    pub fn length(self) -> f32 {
+        self.dot(self).sqrt()
+    }
 }
 
 impl From<TVec3> for Vec3 {
@@ -268,6 +319,19 @@ impl FromFlat<Option<Box<fb::BallHitInfo>>> for BallHitInfo {
     }
 }
 
+impl ToDict for BallHitInfo {
+    fn to_dict<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        py_dict!(py, {
+            "is_valid": self.is_valid,
+            "relative_pos_on_ball": self.relative_pos_on_ball.to_array(),
+            "ball_pos": self.ball_pos.to_array(),
+            "extra_hit_vel": self.extra_hit_vel.to_array(),
+            "tick_count_when_hit": self.tick_count_when_hit,
+            "tick_count_when_extra_impulse_applied": self.tick_count_when_extra_impulse_applied,
+        })
+    }
+}
+
 // ---------------------------------------------------------------------------
 // BallState
 // ---------------------------------------------------------------------------
@@ -369,6 +433,25 @@ impl FromFlat<fb::BallState> for BallState {
     }
 }
 
+impl ToDict for BallState {
+    fn to_dict<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        py_dict!(py, {
+            "pos": self.pos.to_array(),
+            "rot_mat": self.rot_mat.to_array(),
+            "vel": self.vel.to_array(),
+            "ang_vel": self.ang_vel.to_array(),
+            "heatseeker_target_dir": self.heatseeker_target_dir,
+            "heatseeker_target_speed": self.heatseeker_target_speed,
+            "heatseeker_time_since_hit": self.heatseeker_time_since_hit,
+            "dropshot_charge_level": self.dropshot_charge_level,
+            "dropshot_accumulated_hit_force": self.dropshot_accumulated_hit_force,
+            "dropshot_target_dir": self.dropshot_target_dir,
+            "dropshot_has_damaged": self.dropshot_has_damaged,
+            "dropshot_last_damage_tick": self.dropshot_last_damage_tick,
+        })
+    }
+}
+
 // ---------------------------------------------------------------------------
 // Team
 // ---------------------------------------------------------------------------
@@ -383,11 +466,11 @@ pub enum Team {
 
 impl Team {
     #[inline]
-    pub fn from_u8(value: u8) -> Self {
+    pub const fn from_u8(value: u8) -> Option<Self> {
         match value {
-            0 => Self::Blue,
-            1 => Self::Orange,
-            _ => panic!("Invalid team value: {value}"),
+            0 => Some(Self::Blue),
+            1 => Some(Self::Orange),
+            _ => None,
         }
     }
 }
@@ -445,6 +528,16 @@ impl FromFlat<fb::WheelPairConfig> for WheelPairConfig {
     }
 }
 
+impl ToDict for WheelPairConfig {
+    fn to_dict<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        py_dict!(py, {
+            "wheel_radius": self.wheel_radius,
+            "suspension_rest_length": self.suspension_rest_length,
+            "connection_point_offset": self.connection_point_offset.to_array(),
+        })
+    }
+}
+
 // ---------------------------------------------------------------------------
 // CarConfig
 // ---------------------------------------------------------------------------
@@ -488,6 +581,19 @@ impl FromFlat<fb::CarConfig> for CarConfig {
     }
 }
 
+impl ToDict for CarConfig {
+    fn to_dict<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        py_dict!(py, {
+            "hitbox_size": self.hitbox_size.to_array(),
+            "hitbox_pos_offset": self.hitbox_pos_offset.to_array(),
+            "front_wheels": self.front_wheels.to_dict(py)?,
+            "back_wheels": self.back_wheels.to_dict(py)?,
+            "three_wheels": self.three_wheels,
+            "dodge_deadzone": self.dodge_deadzone,
+        })
+    }
+}
+
 // ---------------------------------------------------------------------------
 // CarControls
 // ---------------------------------------------------------------------------
@@ -536,6 +642,21 @@ impl FromFlat<fb::CarControls> for CarControls {
     }
 }
 
+impl ToDict for CarControls {
+    fn to_dict<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        py_dict!(py, {
+            "throttle": self.throttle,
+            "steer": self.steer,
+            "pitch": self.pitch,
+            "yaw": self.yaw,
+            "roll": self.roll,
+            "boost": self.boost,
+            "jump": self.jump,
+            "handbrake": self.handbrake,
+        })
+    }
+}
+
 // ---------------------------------------------------------------------------
 // CarState
 // ---------------------------------------------------------------------------
@@ -572,7 +693,7 @@ pub struct CarState {
     pub auto_flip_torque_scale: f32,
     pub has_world_contact: bool,
     pub world_contact_normal: Vec3,
-    pub car_contact_id: u32,
+    pub car_contact_id: u64,
     pub car_contact_cooldown_timer: f32,
     pub is_demoed: bool,
     pub demo_respawn_timer: f32,
@@ -620,7 +741,7 @@ impl ToFlat for CarState {
             },
             car_contact: if self.car_contact_id != 0 {
                 Some(Box::new(fb::CarContact {
-                    other_car_id: u64::from(self.car_contact_id),
+                    other_car_id: self.car_contact_id,
                     cooldown_timer: self.car_contact_cooldown_timer,
                 }))
             } else {
@@ -643,7 +764,7 @@ impl FromFlat<fb::CarState> for CarState {
             None => (false, Vec3::ZERO),
         };
         let (car_contact_id, car_contact_cooldown_timer) = match value.car_contact {
-            Some(contact) => (contact.other_car_id as u32, contact.cooldown_timer),
+            Some(contact) => (contact.other_car_id, contact.cooldown_timer),
             None => (0, 0.0),
         };
 
@@ -691,20 +812,61 @@ impl FromFlat<fb::CarState> for CarState {
     }
 }
 
+impl ToDict for CarState {
+    fn to_dict<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        py_dict!(py, {
+            "pos": self.pos.to_array(),
+            "rot_mat": self.rot_mat.to_array(),
+            "vel": self.vel.to_array(),
+            "ang_vel": self.ang_vel.to_array(),
+            "is_on_ground": self.is_on_ground,
+            "wheels_with_contact": self.wheels_with_contact,
+            "has_jumped": self.has_jumped,
+            "has_double_jumped": self.has_double_jumped,
+            "has_flipped": self.has_flipped,
+            "flip_rel_torque": self.flip_rel_torque.to_array(),
+            "jump_time": self.jump_time,
+            "flip_time": self.flip_time,
+            "is_flipping": self.is_flipping,
+            "is_jumping": self.is_jumping,
+            "air_time": self.air_time,
+            "air_time_since_jump": self.air_time_since_jump,
+            "boost": self.boost,
+            "time_spent_boosting": self.time_spent_boosting,
+            "is_boosting": self.is_boosting,
+            "boosting_time": self.boosting_time,
+            "is_supersonic": self.is_supersonic,
+            "supersonic_time": self.supersonic_time,
+            "handbrake_val": self.handbrake_val,
+            "is_auto_flipping": self.is_auto_flipping,
+            "auto_flip_timer": self.auto_flip_timer,
+            "auto_flip_torque_scale": self.auto_flip_torque_scale,
+            "has_world_contact": self.has_world_contact,
+            "world_contact_normal": self.world_contact_normal.to_array(),
+            "car_contact_id": self.car_contact_id,
+            "car_contact_cooldown_timer": self.car_contact_cooldown_timer,
+            "is_demoed": self.is_demoed,
+            "demo_respawn_timer": self.demo_respawn_timer,
+            "ball_hit_info": self.ball_hit_info.to_dict(py)?,
+            "last_controls": self.last_controls.to_dict(py)?,
+        })
+    }
+}
+
 // ---------------------------------------------------------------------------
 // CarInfo
 // ---------------------------------------------------------------------------
 
 #[derive(Clone, Copy, Default, Debug)]
 pub struct CarInfo {
-    pub id: u32,
+    pub id: u64,
     pub team: Team,
     pub state: CarState,
     pub config: CarConfig,
 }
 
 pub type TCar = (
-    u32,
+    u64,
     TVec3,
     TRotMat,
     TVec3,
@@ -739,7 +901,7 @@ impl ToFlat for CarInfo {
 
     fn to_flat(&self) -> Self::Flat {
         fb::CarInfo {
-            id: u64::from(self.id),
+            id: self.id,
             team: self.team.to_flat(),
             state: self.state.to_flat(),
             config: self.config.to_flat(),
@@ -750,7 +912,7 @@ impl ToFlat for CarInfo {
 impl FromFlat<fb::CarInfo> for CarInfo {
     fn from_flat(value: fb::CarInfo) -> Self {
         Self {
-            id: value.id as u32,
+            id: value.id,
             team: Team::from_flat(value.team),
             state: CarState::from_flat(*value.state),
             config: CarConfig::from_flat(value.config),
@@ -758,16 +920,56 @@ impl FromFlat<fb::CarInfo> for CarInfo {
     }
 }
 
+impl ToDict for CarInfo {
+    fn to_dict<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        py_dict!(py, {
+            "id": self.id,
+            "team": self.team as u8,
+            "config": self.config.to_dict(py)?,
+            "state": self.state.to_dict(py)?,
+        })
+    }
+}
+
 // ---------------------------------------------------------------------------
 // BoostPadState
 // ---------------------------------------------------------------------------
 
-#[derive(Clone, Copy, Default, Debug)]
+#[derive(Clone, Copy, Default, Debug, FromPyObject)]
 pub struct BoostPadState {
     pub is_active: bool,
     pub cooldown: f32,
-    pub cur_locked_car_id: u32,
-    pub prev_locked_car_id: u32,
+    pub cur_locked_car_id: u64,
+    pub prev_locked_car_id: u64,
+}
+
+/// The state of a boost pad as given to `render`: just whether it's active,
+/// a `(is_active, cooldown, cur_locked_car_id, prev_locked_car_id)` tuple or a full state object
+#[derive(Clone, Copy, Debug, FromPyObject)]
+pub enum BoostPadStateArg {
+    IsActive(bool),
+    Tuple(bool, f32, u64, u64),
+    State(BoostPadState),
+}
+
+impl From<BoostPadStateArg> for BoostPadState {
+    fn from(value: BoostPadStateArg) -> Self {
+        match value {
+            BoostPadStateArg::IsActive(is_active) => Self {
+                is_active,
+                ..Default::default()
+            },
+            BoostPadStateArg::Tuple(is_active, cooldown, cur_locked_car_id, prev_locked_car_id) => {
+                Self {
+                    is_active,
+                    cooldown,
+                    cur_locked_car_id,
+                    prev_locked_car_id,
+                }
+            }
+            BoostPadStateArg::State(state) => state,
+        }
+    }
 }
 
 impl ToFlat for BoostPadState {
@@ -777,8 +979,8 @@ impl ToFlat for BoostPadState {
         fb::BoostPadState {
             is_active: self.is_active,
             cooldown: self.cooldown,
-            cur_locked_car: u64::from(self.cur_locked_car_id),
-            prev_locked_car_id: u64::from(self.prev_locked_car_id),
+            cur_locked_car: self.cur_locked_car_id,
+            prev_locked_car_id: self.prev_locked_car_id,
         }
     }
 }
@@ -788,12 +990,23 @@ impl FromFlat<fb::BoostPadState> for BoostPadState {
         Self {
             is_active: value.is_active,
             cooldown: value.cooldown,
-            cur_locked_car_id: value.cur_locked_car as u32,
-            prev_locked_car_id: value.prev_locked_car_id as u32,
+            cur_locked_car_id: value.cur_locked_car,
+            prev_locked_car_id: value.prev_locked_car_id,
         }
     }
 }
 
+impl ToDict for BoostPadState {
+    fn to_dict<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        py_dict!(py, {
+            "is_active": self.is_active,
+            "cooldown": self.cooldown,
+            "cur_locked_car_id": self.cur_locked_car_id,
+            "prev_locked_car_id": self.prev_locked_car_id,
+        })
+    }
+}
+
 // ---------------------------------------------------------------------------
 // BoostPad
 // ---------------------------------------------------------------------------
@@ -829,6 +1042,113 @@ impl FromFlat<fb::BoostPadInfo> for BoostPad {
     }
 }
 
+impl ToDict for BoostPad {
+    fn to_dict<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        py_dict!(py, {
+            "pos": self.position.to_array(),
+            "is_big": self.is_big,
+            "state": self.state.to_dict(py)?,
+        })
+    }
+}
+
+// ---------------------------------------------------------------------------
+// TileState
+// ---------------------------------------------------------------------------
+
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TileState {
+    #[default]
+    Full,
+    Damaged,
+    Broken,
+}
+
+impl TileState {
+    pub const fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(Self::Full),
+            1 => Some(Self::Damaged),
+            2 => Some(Self::Broken),
+            _ => None,
+        }
+    }
+}
+
+impl ToFlat for TileState {
+    type Flat = fb::TileState;
+
+    fn to_flat(&self) -> Self::Flat {
+        match self {
+            Self::Full => fb::TileState::Full,
+            Self::Damaged => fb::TileState::Damaged,
+            Self::Broken => fb::TileState::Broken,
+        }
+    }
+}
+
+impl FromFlat<fb::TileState> for TileState {
+    fn from_flat(value: fb::TileState) -> Self {
+        match value {
+            fb::TileState::Full => Self::Full,
+            fb::TileState::Damaged => Self::Damaged,
+            fb::TileState::Broken => Self::Broken,
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// DropshotTile
+// ---------------------------------------------------------------------------
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DropshotTile {
+    pub pos: Vec3,
+    pub state: TileState,
+}
+
+pub type TTile = (TVec3, u8);
+
+/// Tiles of the blue and orange sides, in that order
+pub type TTiles = [Vec<TTile>; 2];
+
+impl DropshotTile {
+    #[inline]
+    pub const fn to_array(self) -> TTile {
+        (self.pos.to_array(), self.state as u8)
+    }
+}
+
+impl ToFlat for DropshotTile {
+    type Flat = fb::DropshotTile;
+
+    fn to_flat(&self) -> Self::Flat {
+        fb::DropshotTile {
+            pos: self.pos.to_flat(),
+            state: self.state.to_flat(),
+        }
+    }
+}
+
+impl FromFlat<fb::DropshotTile> for DropshotTile {
+    fn from_flat(value: fb::DropshotTile) -> Self {
+        Self {
+            pos: Vec3::from_flat(value.pos),
+            state: TileState::from_flat(value.state),
+        }
+    }
+}
+
+impl ToDict for DropshotTile {
+    fn to_dict<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        py_dict!(py, {
+            "pos": self.pos.to_array(),
+            "state": self.state as u8,
+        })
+    }
+}
+
 // ---------------------------------------------------------------------------
 // GameState
 // ---------------------------------------------------------------------------
@@ -841,6 +1161,8 @@ pub struct GameState {
     pub ball: BallState,
     pub pads: Vec<BoostPad>,
     pub cars: Vec<CarInfo>,
+    /// Only sent in Dropshot, as `[blue_tiles, orange_tiles]`
+    pub tiles: Option<[Vec<DropshotTile>; 2]>,
 }
 
 impl ToFlat for GameState {
@@ -854,7 +1176,12 @@ impl ToFlat for GameState {
             cars: Some(self.cars.iter().map(ToFlat::to_flat).collect()),
             ball: self.ball.to_flat(),
             pads: Some(self.pads.iter().map(ToFlat::to_flat).collect()),
-            tiles: None,
+            tiles: self.tiles.as_ref().map(|[blue_tiles, orange_tiles]| {
+                Box::new(fb::DropshotTilesByTeam {
+                    blue_tiles: blue_tiles.iter().map(ToFlat::to_flat).collect(),
+                    orange_tiles: orange_tiles.iter().map(ToFlat::to_flat).collect(),
+                })
+            }),
         }
     }
 }
@@ -878,6 +1205,31 @@ impl FromFlat<fb::GameState> for GameState {
                 .flatten()
                 .map(CarInfo::from_flat)
                 .collect(),
+            tiles: value.tiles.map(|tiles| {
+                [tiles.blue_tiles, tiles.orange_tiles]
+                    .map(|tiles| tiles.into_iter().map(DropshotTile::from_flat).collect())
+            }),
         }
     }
 }
+
+impl ToDict for GameState {
+    fn to_dict<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        let tiles = match &self.tiles {
+            Some([blue_tiles, orange_tiles]) => {
+                Some([to_dicts(py, blue_tiles)?, to_dicts(py, orange_tiles)?])
+            }
+            None => None,
+        };
+
+        py_dict!(py, {
+            "tick_count": self.tick_count,
+            "tick_rate": self.tick_rate,
+            "game_mode": self.game_mode as u8,
+            "ball": self.ball.to_dict(py)?,
+            "pads": to_dicts(py, &self.pads)?,
+            "cars": to_dicts(py, &self.cars)?,
+            "tiles": tiles,
+        })
+    }
+}
@@ -0,0 +1,59 @@
+use std::time::{Duration, Instant};
+
+/// How often a heartbeat is sent to RLViser while the viewer is being used
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// RLViser is considered gone after this long without hearing anything from it
+const CONNECTION_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Keeps track of when RLViser was last heard from
+#[derive(Default)]
+pub struct ConnectionTracker {
+    last_seen: Option<Instant>,
+    last_heartbeat: Option<Instant>,
+    connected: bool,
+}
+
+impl ConnectionTracker {
+    pub fn heartbeat_due(&self) -> bool {
+        self.last_heartbeat
+            .is_none_or(|last_heartbeat| last_heartbeat.elapsed() >= HEARTBEAT_INTERVAL)
+    }
+
+    pub fn heartbeat_sent(&mut self) {
+        self.last_heartbeat = Some(Instant::now());
+    }
+
+    pub fn seen(&mut self) {
+        self.last_seen = Some(Instant::now());
+    }
+
+    pub fn last_seen(&self) -> Option<Duration> {
+        self.last_seen.map(|last_seen| last_seen.elapsed())
+    }
+
+    pub const fn is_connected(&self) -> bool {
+        self.connected
+    }
+
+    /// Re-check the connection, returning the new state if it changed
+    pub fn update(&mut self) -> Option<bool> {
+        let connected = self
+            .last_seen()
+            .is_some_and(|last_seen| last_seen < CONNECTION_TIMEOUT);
+
+        if connected == self.connected {
+            return None;
+        }
+
+        self.connected = connected;
+        Some(connected)
+    }
+
+    /// Forget about the current connection, like after telling RLViser to quit
+    pub fn reset(&mut self) -> Option<bool> {
+        self.last_seen = None;
+        self.last_heartbeat = None;
+        self.update()
+    }
+}
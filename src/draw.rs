@@ -0,0 +1,517 @@
+use pyo3::prelude::*;
+
+use crate::{
+    bytes::{RotMat, TRotMat, TVec3, ToFlat, Vec3},
+    flat::rocketsim as fb,
+};
+
+// ---------------------------------------------------------------------------
+// Vec2
+// ---------------------------------------------------------------------------
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Vec2 {
+    pub x: f32,
+    pub y: f32,
+}
+
+pub type TVec2 = [f32; 2];
+
+impl Vec2 {
+    pub const fn new(x: f32, y: f32) -> Self {
+        Self { x, y }
+    }
+
+    pub const fn from_array(array: TVec2) -> Self {
+        Self::new(array[0], array[1])
+    }
+}
+
+impl ToFlat for Vec2 {
+    type Flat = fb::Vec2;
+
+    fn to_flat(&self) -> Self::Flat {
+        fb::Vec2 {
+            x: self.x,
+            y: self.y,
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Color
+// ---------------------------------------------------------------------------
+
+#[derive(Clone, Copy, Debug)]
+pub struct Color {
+    pub r: f32,
+    pub g: f32,
+    pub b: f32,
+    pub a: f32,
+}
+
+pub type TColor = [f32; 4];
+
+impl Color {
+    pub const fn from_array(array: TColor) -> Self {
+        Self {
+            r: array[0],
+            g: array[1],
+            b: array[2],
+            a: array[3],
+        }
+    }
+}
+
+impl ToFlat for Color {
+    type Flat = fb::Color;
+
+    fn to_flat(&self) -> Self::Flat {
+        fb::Color {
+            r: self.r,
+            g: self.g,
+            b: self.b,
+            a: self.a,
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Anchor
+// ---------------------------------------------------------------------------
+
+/// What the coordinates of a world space render command are relative to
+///
+/// - `Anchor.World()` - absolute world space (the default)
+/// - `Anchor.Car(id)` - offsets in the local (forward, right, up) frame of the car with the given id
+/// - `Anchor.Ball()` - offsets from the position of the ball
+#[pyclass(frozen, from_py_object, module = "rlviser_py")]
+#[derive(Clone, Copy, Debug)]
+pub enum Anchor {
+    World(),
+    Car { id: u64 },
+    Ball(),
+}
+
+impl Default for Anchor {
+    #[inline]
+    fn default() -> Self {
+        Self::World()
+    }
+}
+
+impl ToFlat for Anchor {
+    type Flat = Option<Box<fb::RenderAnchor>>;
+
+    fn to_flat(&self) -> Self::Flat {
+        let (target, car_id) = match *self {
+            // world space is what RLViser assumes when there's no anchor
+            Self::World() => return None,
+            Self::Car { id } => (fb::AnchorTarget::Car, id),
+            Self::Ball() => (fb::AnchorTarget::Ball, 0),
+        };
+
+        Some(Box::new(fb::RenderAnchor { target, car_id }))
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Render
+// ---------------------------------------------------------------------------
+
+#[derive(Clone, Debug)]
+pub enum Render {
+    Line2D {
+        start: Vec2,
+        end: Vec2,
+        color: Color,
+    },
+    Line3D {
+        start: Vec3,
+        end: Vec3,
+        color: Color,
+        anchor: Anchor,
+    },
+    LineStrip {
+        positions: Vec<Vec3>,
+        color: Color,
+        anchor: Anchor,
+    },
+    Text2D {
+        text: String,
+        position: Vec2,
+        scale: f32,
+        color: Color,
+        background: Option<Color>,
+    },
+    Text3D {
+        text: String,
+        position: Vec3,
+        scale: f32,
+        color: Color,
+        background: Option<Color>,
+        anchor: Anchor,
+    },
+    Sphere {
+        center: Vec3,
+        radius: f32,
+        color: Color,
+        filled: bool,
+        anchor: Anchor,
+    },
+    OrientedBox {
+        center: Vec3,
+        rot_mat: RotMat,
+        size: Vec3,
+        color: Color,
+        filled: bool,
+        anchor: Anchor,
+    },
+    Cylinder {
+        start: Vec3,
+        end: Vec3,
+        radius: f32,
+        color: Color,
+        filled: bool,
+        anchor: Anchor,
+    },
+    Arrow {
+        start: Vec3,
+        end: Vec3,
+        head_size: f32,
+        color: Color,
+        filled: bool,
+        anchor: Anchor,
+    },
+}
+
+impl ToFlat for Render {
+    type Flat = fb::Render;
+
+    fn to_flat(&self) -> Self::Flat {
+        match self {
+            Self::Line2D { start, end, color } => fb::Render::Line2D(Box::new(fb::Line2D {
+                start: start.to_flat(),
+                end: end.to_flat(),
+                color: color.to_flat(),
+            })),
+            Self::Line3D {
+                start,
+                end,
+                color,
+                anchor,
+            } => fb::Render::Line3D(Box::new(fb::Line3D {
+                start: start.to_flat(),
+                end: end.to_flat(),
+                color: color.to_flat(),
+                anchor: anchor.to_flat(),
+            })),
+            Self::LineStrip {
+                positions,
+                color,
+                anchor,
+            } => fb::Render::LineStrip(Box::new(fb::LineStrip {
+                positions: positions.iter().map(ToFlat::to_flat).collect(),
+                color: color.to_flat(),
+                anchor: anchor.to_flat(),
+            })),
+            Self::Text2D {
+                text,
+                position,
+                scale,
+                color,
+                background,
+            } => fb::Render::Text2D(Box::new(fb::Text2D {
+                text: text.clone(),
+                position: position.to_flat(),
+                scale: *scale,
+                color: color.to_flat(),
+                background: background.as_ref().map(ToFlat::to_flat),
+            })),
+            Self::Text3D {
+                text,
+                position,
+                scale,
+                color,
+                background,
+                anchor,
+            } => fb::Render::Text3D(Box::new(fb::Text3D {
+                text: text.clone(),
+                position: position.to_flat(),
+                scale: *scale,
+                color: color.to_flat(),
+                background: background.as_ref().map(ToFlat::to_flat),
+                anchor: anchor.to_flat(),
+            })),
+            Self::Sphere {
+                center,
+                radius,
+                color,
+                filled,
+                anchor,
+            } => fb::Render::Sphere(Box::new(fb::Sphere {
+                center: center.to_flat(),
+                radius: *radius,
+                color: color.to_flat(),
+                filled: *filled,
+                anchor: anchor.to_flat(),
+            })),
+            Self::OrientedBox {
+                center,
+                rot_mat,
+                size,
+                color,
+                filled,
+                anchor,
+            } => fb::Render::OrientedBox(Box::new(fb::OrientedBox {
+                center: center.to_flat(),
+                rot_mat: rot_mat.to_flat(),
+                size: size.to_flat(),
+                color: color.to_flat(),
+                filled: *filled,
+                anchor: anchor.to_flat(),
+            })),
+            Self::Cylinder {
+                start,
+                end,
+                radius,
+                color,
+                filled,
+                anchor,
+            } => fb::Render::Cylinder(Box::new(fb::Cylinder {
+                start: start.to_flat(),
+                end: end.to_flat(),
+                radius: *radius,
+                color: color.to_flat(),
+                filled: *filled,
+                anchor: anchor.to_flat(),
+            })),
+            Self::Arrow {
+                start,
+                end,
+                head_size,
+                color,
+                filled,
+                anchor,
+            } => fb::Render::Arrow(Box::new(fb::Arrow {
+                start: start.to_flat(),
+                end: end.to_flat(),
+                head_size: *head_size,
+                color: color.to_flat(),
+                filled: *filled,
+                anchor: anchor.to_flat(),
+            })),
+        }
+    }
+}
+
+/// A single debug render primitive, created by one of the builder functions
+#[pyclass(frozen, from_py_object, module = "rlviser_py")]
+#[derive(Clone, Debug)]
+pub struct RenderCommand(pub Render);
+
+#[pymethods]
+impl RenderCommand {
+    fn __repr__(&self) -> String {
+        format!("RenderCommand({:?})", self.0)
+    }
+}
+
+// ---------------------------------------------------------------------------
+// RenderGroup
+// ---------------------------------------------------------------------------
+
+#[derive(Clone, Copy, Debug)]
+pub enum RenderLifetime {
+    Seconds(f32),
+    Ticks(u64),
+}
+
+#[derive(Clone, Debug)]
+pub struct RenderGroup {
+    pub id: i32,
+    pub commands: Vec<Render>,
+    pub lifetime: Option<RenderLifetime>,
+}
+
+impl ToFlat for RenderGroup {
+    type Flat = fb::AddRender;
+
+    fn to_flat(&self) -> Self::Flat {
+        let (lifetime_seconds, lifetime_ticks) = match self.lifetime {
+            Some(RenderLifetime::Seconds(seconds)) => (Some(seconds), None),
+            Some(RenderLifetime::Ticks(ticks)) => (None, Some(ticks)),
+            None => (None, None),
+        };
+
+        fb::AddRender {
+            id: self.id,
+            commands: self.commands.iter().map(ToFlat::to_flat).collect(),
+            lifetime_seconds,
+            lifetime_ticks,
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Python builders
+// ---------------------------------------------------------------------------
+
+/// A line between two points in screen space
+#[pyfunction]
+pub fn line_2d(start: TVec2, end: TVec2, color: TColor) -> RenderCommand {
+    RenderCommand(Render::Line2D {
+        start: Vec2::from_array(start),
+        end: Vec2::from_array(end),
+        color: Color::from_array(color),
+    })
+}
+
+/// A line between two points in world space
+#[pyfunction]
+#[pyo3(signature = (start, end, color, anchor=None))]
+pub fn line_3d(start: TVec3, end: TVec3, color: TColor, anchor: Option<Anchor>) -> RenderCommand {
+    RenderCommand(Render::Line3D {
+        start: Vec3::from_array(start),
+        end: Vec3::from_array(end),
+        color: Color::from_array(color),
+        anchor: anchor.unwrap_or_default(),
+    })
+}
+
+/// Connected line segments through each of the points in world space
+#[pyfunction]
+#[pyo3(signature = (positions, color, anchor=None))]
+pub fn line_strip(positions: Vec<TVec3>, color: TColor, anchor: Option<Anchor>) -> RenderCommand {
+    RenderCommand(Render::LineStrip {
+        positions: positions.into_iter().map(Vec3::from_array).collect(),
+        color: Color::from_array(color),
+        anchor: anchor.unwrap_or_default(),
+    })
+}
+
+/// Text in screen space, anchored at its top-left corner
+#[pyfunction]
+#[pyo3(signature = (text, position, color, scale=1.0, background=None))]
+pub fn text_2d(
+    text: String,
+    position: TVec2,
+    color: TColor,
+    scale: f32,
+    background: Option<TColor>,
+) -> RenderCommand {
+    RenderCommand(Render::Text2D {
+        text,
+        position: Vec2::from_array(position),
+        scale,
+        color: Color::from_array(color),
+        background: background.map(Color::from_array),
+    })
+}
+
+/// Text centered on a point in world space, always facing the camera
+#[pyfunction]
+#[pyo3(signature = (text, position, color, scale=1.0, background=None, anchor=None))]
+pub fn text_3d(
+    text: String,
+    position: TVec3,
+    color: TColor,
+    scale: f32,
+    background: Option<TColor>,
+    anchor: Option<Anchor>,
+) -> RenderCommand {
+    RenderCommand(Render::Text3D {
+        text,
+        position: Vec3::from_array(position),
+        scale,
+        color: Color::from_array(color),
+        background: background.map(Color::from_array),
+        anchor: anchor.unwrap_or_default(),
+    })
+}
+
+/// A sphere centered on a point in world space
+#[pyfunction]
+#[pyo3(signature = (center, radius, color, filled=false, anchor=None))]
+pub fn sphere(
+    center: TVec3,
+    radius: f32,
+    color: TColor,
+    filled: bool,
+    anchor: Option<Anchor>,
+) -> RenderCommand {
+    RenderCommand(Render::Sphere {
+        center: Vec3::from_array(center),
+        radius,
+        color: Color::from_array(color),
+        filled,
+        anchor: anchor.unwrap_or_default(),
+    })
+}
+
+/// A box centered on a point in world space, rotated by `rot_mat` (forward, right, up)
+#[pyfunction]
+#[pyo3(signature = (center, rot_mat, size, color, filled=false, anchor=None))]
+pub fn oriented_box(
+    center: TVec3,
+    rot_mat: TRotMat,
+    size: TVec3,
+    color: TColor,
+    filled: bool,
+    anchor: Option<Anchor>,
+) -> RenderCommand {
+    RenderCommand(Render::OrientedBox {
+        center: Vec3::from_array(center),
+        rot_mat: RotMat::new(
+            Vec3::from_array(rot_mat[0]),
+            Vec3::from_array(rot_mat[1]),
+            Vec3::from_array(rot_mat[2]),
+        ),
+        size: Vec3::from_array(size),
+        color: Color::from_array(color),
+        filled,
+        anchor: anchor.unwrap_or_default(),
+    })
+}
+
+/// A cylinder between the centers of its two circular faces in world space
+#[pyfunction]
+#[pyo3(signature = (start, end, radius, color, filled=false, anchor=None))]
+pub fn cylinder(
+    start: TVec3,
+    end: TVec3,
+    radius: f32,
+    color: TColor,
+    filled: bool,
+    anchor: Option<Anchor>,
+) -> RenderCommand {
+    RenderCommand(Render::Cylinder {
+        start: Vec3::from_array(start),
+        end: Vec3::from_array(end),
+        radius,
+        color: Color::from_array(color),
+        filled,
+        anchor: anchor.unwrap_or_default(),
+    })
+}
+
+/// An arrow pointing from `start` to `end` in world space
+#[pyfunction]
+#[pyo3(signature = (start, end, color, head_size=20.0, filled=false, anchor=None))]
+pub fn arrow(
+    start: TVec3,
+    end: TVec3,
+    color: TColor,
+    head_size: f32,
+    filled: bool,
+    anchor: Option<Anchor>,
+) -> RenderCommand {
+    RenderCommand(Render::Arrow {
+        start: Vec3::from_array(start),
+        end: Vec3::from_array(end),
+        head_size,
+        color: Color::from_array(color),
+        filled,
+        anchor: anchor.unwrap_or_default(),
+    })
+}
@@ -0,0 +1,69 @@
+use pyo3::{PyErr, create_exception, exceptions::PyException};
+use std::{fmt, io};
+
+create_exception!(
+    rlviser_py,
+    RLViserError,
+    PyException,
+    "Base class for all of the errors raised by rlviser_py"
+);
+create_exception!(
+    rlviser_py,
+    BindError,
+    RLViserError,
+    "The socket used to talk to RLViser couldn't be set up"
+);
+create_exception!(
+    rlviser_py,
+    SendError,
+    RLViserError,
+    "A message couldn't be sent to RLViser"
+);
+create_exception!(
+    rlviser_py,
+    LaunchError,
+    RLViserError,
+    "The RLViser executable couldn't be started"
+);
+create_exception!(
+    rlviser_py,
+    ProtocolError,
+    RLViserError,
+    "A message couldn't be encoded or decoded"
+);
+
+#[derive(Debug)]
+pub enum Error {
+    Bind(io::Error),
+    Send(io::Error),
+    /// The path of the executable and why it couldn't be started
+    Launch(String, io::Error),
+    Protocol(String),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Bind(e) => write!(f, "Failed to set up the socket: {e}"),
+            Self::Send(e) => write!(f, "Failed to send a message to RLViser: {e}"),
+            Self::Launch(path, e) => write!(f, "Failed to launch RLViser ({path}): {e}"),
+            Self::Protocol(e) => write!(f, "Protocol error: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<Error> for PyErr {
+    fn from(error: Error) -> Self {
+        let message = error.to_string();
+        match error {
+            Error::Bind(_) => BindError::new_err(message),
+            Error::Send(_) => SendError::new_err(message),
+            Error::Launch(..) => LaunchError::new_err(message),
+            Error::Protocol(_) => ProtocolError::new_err(message),
+        }
+    }
+}
@@ -0,0 +1,278 @@
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    error::{Error, Result},
+    flat::rocketsim as fb,
+    logging::{self, Level},
+};
+
+/// Largest payload that fits in a single UDP datagram over IPv4
+pub const MAX_DATAGRAM_SIZE: usize = 65507;
+
+/// Room left in each datagram for the length header and the `Packet`/`Fragment` tables
+const FRAGMENT_OVERHEAD: usize = 128;
+const MAX_FRAGMENT_DATA: usize = MAX_DATAGRAM_SIZE - FRAGMENT_OVERHEAD;
+
+/// Messages that are still missing fragments after this long are dropped
+const FRAGMENT_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// How many fragments a payload of `len` bytes is split into
+fn fragment_count(len: usize) -> Result<u16> {
+    u16::try_from(len.div_ceil(MAX_FRAGMENT_DATA))
+        .map_err(|_| Error::Protocol(format!("message of {len} bytes is too large to send")))
+}
+
+/// Split an encoded `Packet` into fragments that each fit in a single datagram
+pub fn split(message_id: u32, payload: &[u8]) -> Result<Vec<fb::Fragment>> {
+    let count = fragment_count(payload.len())?;
+
+    Ok(payload
+        .chunks(MAX_FRAGMENT_DATA)
+        .zip(0..)
+        .map(|(data, index)| fb::Fragment {
+            message_id,
+            index,
+            count,
+            data: data.to_vec(),
+        })
+        .collect())
+}
+
+struct PartialMessage {
+    first_received: Instant,
+    fragments: Vec<Option<Vec<u8>>>,
+    missing: usize,
+}
+
+impl PartialMessage {
+    fn new(count: u16) -> Self {
+        Self {
+            first_received: Instant::now(),
+            fragments: vec![None; usize::from(count)],
+            missing: usize::from(count),
+        }
+    }
+}
+
+/// Collects fragments until every part of a message has arrived
+#[derive(Default)]
+pub struct Reassembler {
+    partial: HashMap<(SocketAddr, u32), PartialMessage>,
+}
+
+impl Reassembler {
+    /// Store a fragment, returning the full `Packet` payload once it's the last one missing
+    pub fn insert(&mut self, src: SocketAddr, fragment: fb::Fragment) -> Option<Vec<u8>> {
+        if fragment.index >= fragment.count {
+            logging::diagnostic(
+                Level::Warning,
+                "decode_error",
+                &[("bytes", fragment.data.len().into())],
+                || {
+                    format!(
+                        "Dropped fragment {}/{} of message {} from {src}, its index is out of range",
+                        fragment.index, fragment.count, fragment.message_id
+                    )
+                },
+            );
+            return None;
+        }
+
+        let key = (src, fragment.message_id);
+        let partial = self
+            .partial
+            .entry(key)
+            .or_insert_with(|| PartialMessage::new(fragment.count));
+
+        if partial.fragments.len() != usize::from(fragment.count) {
+            // the sender reused a message id, the old message is never going to complete
+            *partial = PartialMessage::new(fragment.count);
+        }
+
+        let slot = &mut partial.fragments[usize::from(fragment.index)];
+        if slot.is_none() {
+            *slot = Some(fragment.data);
+            partial.missing -= 1;
+        }
+
+        if partial.missing != 0 {
+            return None;
+        }
+
+        let partial = self.partial.remove(&key)?;
+        Some(partial.fragments.into_iter().flatten().flatten().collect())
+    }
+
+    /// Drop the messages that didn't receive all of their fragments in time
+    pub fn remove_expired(&mut self) {
+        self.partial.retain(|&(src, message_id), partial| {
+            if partial.first_received.elapsed() < FRAGMENT_TIMEOUT {
+                return true;
+            }
+
+            let count = partial.fragments.len();
+            logging::diagnostic(
+                Level::Warning,
+                "fragment_timeout",
+                &[
+                    ("message_id", (message_id as usize).into()),
+                    ("missing", partial.missing.into()),
+                    ("count", count.into()),
+                ],
+                || {
+                    format!(
+                        "Dropped message {message_id} from {src}, {} of its {count} fragments never arrived",
+                        partial.missing
+                    )
+                },
+            );
+
+            false
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SRC: SocketAddr =
+        SocketAddr::new(std::net::IpAddr::V4(std::net::Ipv4Addr::LOCALHOST), 45243);
+
+    fn payload(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i % 251) as u8).collect()
+    }
+
+    fn fragment(message_id: u32, index: u16, count: u16, data: &[u8]) -> fb::Fragment {
+        fb::Fragment {
+            message_id,
+            index,
+            count,
+            data: data.to_vec(),
+        }
+    }
+
+    #[test]
+    fn split_fits_each_fragment_in_a_datagram() {
+        let payload = payload(MAX_FRAGMENT_DATA * 2 + 1);
+        let fragments = split(7, &payload).unwrap();
+
+        assert_eq!(fragments.len(), 3);
+        for (i, fragment) in fragments.iter().enumerate() {
+            assert_eq!(fragment.message_id, 7);
+            assert_eq!(usize::from(fragment.index), i);
+            assert_eq!(fragment.count, 3);
+            assert!(fragment.data.len() <= MAX_FRAGMENT_DATA);
+        }
+    }
+
+    #[test]
+    fn split_then_insert_round_trips() {
+        let payload = payload(MAX_FRAGMENT_DATA * 2 + 1);
+        let mut fragments = split(1, &payload).unwrap();
+        let last = fragments.pop().unwrap();
+        let mut reassembler = Reassembler::default();
+
+        for fragment in fragments {
+            assert_eq!(reassembler.insert(SRC, fragment), None);
+        }
+
+        assert_eq!(reassembler.insert(SRC, last), Some(payload));
+        assert!(reassembler.partial.is_empty());
+    }
+
+    #[test]
+    fn insert_handles_out_of_order_fragments() {
+        let payload = payload(MAX_FRAGMENT_DATA * 3);
+        let mut fragments = split(2, &payload).unwrap();
+        fragments.swap(0, 2);
+        let last = fragments.pop().unwrap();
+        let mut reassembler = Reassembler::default();
+
+        for fragment in fragments {
+            assert_eq!(reassembler.insert(SRC, fragment), None);
+        }
+
+        assert_eq!(reassembler.insert(SRC, last), Some(payload));
+    }
+
+    #[test]
+    fn insert_ignores_duplicate_fragments() {
+        let mut reassembler = Reassembler::default();
+
+        assert_eq!(reassembler.insert(SRC, fragment(3, 0, 2, b"ab")), None);
+        assert_eq!(reassembler.insert(SRC, fragment(3, 0, 2, b"xx")), None);
+        assert_eq!(
+            reassembler.insert(SRC, fragment(3, 1, 2, b"cd")),
+            Some(b"abcd".to_vec())
+        );
+    }
+
+    #[test]
+    fn insert_keeps_messages_from_different_sources_apart() {
+        let other = SocketAddr::new(SRC.ip(), SRC.port() + 1);
+        let mut reassembler = Reassembler::default();
+
+        assert_eq!(reassembler.insert(SRC, fragment(4, 0, 2, b"ab")), None);
+        assert_eq!(reassembler.insert(other, fragment(4, 1, 2, b"cd")), None);
+        assert_eq!(
+            reassembler.insert(SRC, fragment(4, 1, 2, b"ef")),
+            Some(b"abef".to_vec())
+        );
+    }
+
+    #[test]
+    fn insert_drops_fragments_with_an_out_of_range_index() {
+        let mut reassembler = Reassembler::default();
+
+        assert_eq!(reassembler.insert(SRC, fragment(5, 2, 2, b"ab")), None);
+        assert_eq!(reassembler.insert(SRC, fragment(5, 0, 0, b"ab")), None);
+        assert!(reassembler.partial.is_empty());
+    }
+
+    #[test]
+    fn insert_starts_over_when_a_message_id_is_reused_with_another_count() {
+        let mut reassembler = Reassembler::default();
+
+        assert_eq!(reassembler.insert(SRC, fragment(6, 0, 3, b"old")), None);
+        assert_eq!(reassembler.insert(SRC, fragment(6, 1, 2, b"cd")), None);
+        assert_eq!(
+            reassembler.insert(SRC, fragment(6, 0, 2, b"ab")),
+            Some(b"abcd".to_vec())
+        );
+    }
+
+    #[test]
+    fn remove_expired_drops_old_partial_messages() {
+        let mut reassembler = Reassembler::default();
+        assert_eq!(reassembler.insert(SRC, fragment(8, 0, 2, b"ab")), None);
+        assert_eq!(reassembler.insert(SRC, fragment(9, 0, 2, b"ab")), None);
+
+        reassembler
+            .partial
+            .get_mut(&(SRC, 8))
+            .unwrap()
+            .first_received -= FRAGMENT_TIMEOUT;
+        reassembler.remove_expired();
+
+        assert!(!reassembler.partial.contains_key(&(SRC, 8)));
+        assert!(reassembler.partial.contains_key(&(SRC, 9)));
+    }
+
+    #[test]
+    fn fragment_count_rejects_messages_that_need_too_many_fragments() {
+        let max_len = usize::from(u16::MAX) * MAX_FRAGMENT_DATA;
+
+        assert_eq!(fragment_count(0).unwrap(), 0);
+        assert_eq!(fragment_count(1).unwrap(), 1);
+        assert_eq!(fragment_count(max_len).unwrap(), u16::MAX);
+        assert!(matches!(
+            fragment_count(max_len + 1),
+            Err(Error::Protocol(_))
+        ));
+    }
+}
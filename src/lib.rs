@@ -1,28 +1,43 @@
 #![warn(clippy::all)]
 
-#[allow(clippy::wrong_self_convention)]
+#[allow(clippy::wrong_self_convention, clippy::possible_missing_else)]
 pub mod flat {
     include!(concat!(env!("OUT_DIR"), "/flat.rs"));
 }
 
+mod arrays;
+mod boost_pads;
 mod bytes;
+mod connection;
+mod draw;
+mod error;
+mod fragment;
+mod logging;
+mod process;
 mod socket;
+mod validate;
+mod viewer;
 
-use bytes::{
-    BallState, BoostPad, BoostPadState, CarConfig, CarInfo, CarState, GameMode, GameState, TBall,
-    TCar, Team, Vec3,
-};
-use core::cell::RefCell;
+use arrays::{Array1, Array2, Array3};
+use boost_pads::BoostPadLocation;
+use bytes::{BallState, BoostPadStateArg, CarConfig, TTiles};
+use draw::{Anchor, RenderCommand};
+use error::{BindError, LaunchError, ProtocolError, RLViserError, SendError};
 use pyo3::prelude::*;
+use std::{collections::HashMap, path::PathBuf};
+use viewer::{Car, Viewer, default_viewer};
 
 macro_rules! pynamedmodule {
-    (doc: $doc:literal, name: $name:tt, funcs: [$($func_name:path),*], vars: [$(($var_name:literal, $value:expr)),*]) => {
+    (doc: $doc:literal, name: $name:tt, funcs: [$($func_name:path),*], classes: [$($class_name:ty),*], exceptions: [$($exception_name:ident),*], vars: [$(($var_name:literal, $value:expr)),*], on_exit: $on_exit:path) => {
         #[doc = $doc]
         #[pymodule]
         #[allow(redundant_semicolons)]
         fn $name(_py: Python, m: Bound<PyModule>) -> PyResult<()> {
             $(m.add_function(wrap_pyfunction!($func_name, &m)?)?);*;
+            $(m.add_class::<$class_name>()?);*;
+            $(m.add(stringify!($exception_name), m.py().get_type::<$exception_name>())?);*;
             $(m.add($var_name, $value)?);*;
+            m.py().import("atexit")?.call_method1("register", (wrap_pyfunction!($on_exit, &m)?,))?;
             Ok(())
         }
     };
@@ -33,175 +48,299 @@ pynamedmodule! {
     name: rlviser_py,
     funcs: [
         set_boost_pad_locations,
+        set_validation,
         get_state_set,
         get_game_speed,
         get_game_paused,
         report_game_speed,
         report_game_paused,
+        is_connected,
+        last_seen_seconds,
+        on_connection_change,
+        is_rlviser_running,
+        rlviser_pid,
+        rlviser_exit_code,
+        wait_for_rlviser,
         render,
+        render_arrays,
+        render_arena,
+        draw::line_2d,
+        draw::line_3d,
+        draw::line_strip,
+        draw::text_2d,
+        draw::text_3d,
+        draw::sphere,
+        draw::oriented_box,
+        draw::cylinder,
+        draw::arrow,
+        add_render,
+        remove_render,
+        clear_renders,
         launch,
         quit
     ],
+    classes: [
+        Viewer,
+        RenderCommand,
+        Anchor
+    ],
+    exceptions: [
+        RLViserError,
+        BindError,
+        SendError,
+        LaunchError,
+        ProtocolError
+    ],
     vars: [
         ("__version__", env!("CARGO_PKG_VERSION"))
-    ]
+    ],
+    on_exit: quit_on_exit
 }
 
-thread_local! {
-    static BOOST_PAD_LOCATIONS: RefCell<Vec<Vec3>> = const { RefCell::new(Vec::new()) };
-    static GAME_STATE: RefCell<Option<GameState>> = const { RefCell::new(None) };
-    static GAME_SPEED: RefCell<f32> = const { RefCell::new(1.0) };
-    static GAME_PAUSED: RefCell<bool> = const { RefCell::new(false) };
+/// Set the boost pad locations to send to RLViser in each packet,
+/// or `None` to go back to the standard layout of the game mode being rendered
+#[pyfunction]
+fn set_boost_pad_locations(py: Python, locations: Option<Vec<BoostPadLocation>>) -> PyResult<()> {
+    default_viewer(py)?
+        .get()
+        .set_boost_pad_locations(py, locations);
+    Ok(())
 }
 
-/// Set the boost pad locations to send to RLViser in each packet
+/// Set what `render` does with game states that have non-finite values,
+/// non-orthonormal rotations, objects outside of the arena or boost outside of 0 to 100:
+/// `"off"` (the default), `"warn"`, `"raise"` or `"repair"`
 #[pyfunction]
-fn set_boost_pad_locations(locations: Vec<[f32; 3]>) {
-    BOOST_PAD_LOCATIONS.with_borrow_mut(|locs| {
-        locs.resize(locations.len(), Vec3::ZERO);
-        locs.iter_mut()
-            .zip(locations)
-            .for_each(|(rloc, pyloc)| *rloc = Vec3::from_array(pyloc));
-    });
+fn set_validation(py: Python, mode: &str) -> PyResult<()> {
+    default_viewer(py)?.get().set_validation(py, mode)
 }
 
 #[pyfunction]
-fn get_state_set() -> Option<(Vec<f32>, TBall, Vec<TCar>)> {
-    let return_message = socket::get_return_messages();
-
-    if let Some(speed) = return_message.speed {
-        GAME_SPEED.with_borrow_mut(|speed_cell| *speed_cell = speed);
-    }
-
-    if let Some(paused) = return_message.paused {
-        GAME_PAUSED.with_borrow_mut(|paused_cell| *paused_cell = paused);
-    }
-
-    let game_state = return_message
-        .game_state
-        .or_else(|| GAME_STATE.with_borrow_mut(|state_cell| state_cell.take()))?;
-
-    let pads = game_state
-        .pads
-        .into_iter()
-        .map(|pad| pad.state.cooldown)
-        .collect::<Vec<_>>();
-    let cars = game_state
-        .cars
-        .into_iter()
-        .map(CarInfo::to_array)
-        .collect::<Vec<_>>();
-
-    Some((pads, game_state.ball.to_array(), cars))
+#[pyo3(signature = (include_tiles=false, full=false))]
+fn get_state_set(py: Python, include_tiles: bool, full: bool) -> PyResult<Option<Bound<PyAny>>> {
+    default_viewer(py)?
+        .get()
+        .get_state_set(py, include_tiles, full)
 }
 
 #[pyfunction]
-fn get_game_speed() -> f32 {
-    let return_message = socket::get_return_messages();
-
-    if let Some(paused) = return_message.paused {
-        GAME_PAUSED.with_borrow_mut(|paused_cell| *paused_cell = paused);
-    }
-
-    if let Some(game_state) = return_message.game_state {
-        GAME_STATE.with_borrow_mut(|state_cell| *state_cell = Some(game_state));
-    }
-
-    match return_message.speed {
-        Some(speed) => {
-            GAME_SPEED.with_borrow_mut(|speed_cell| *speed_cell = speed);
-            speed
-        }
-        None => GAME_SPEED.with_borrow(|speed_cell| *speed_cell),
-    }
+fn get_game_speed(py: Python) -> PyResult<f32> {
+    Ok(default_viewer(py)?.get().get_game_speed(py))
 }
 
 #[pyfunction]
-fn get_game_paused() -> bool {
-    let return_message = socket::get_return_messages();
-
-    if let Some(speed) = return_message.speed {
-        GAME_SPEED.with_borrow_mut(|speed_cell| *speed_cell = speed);
-    }
+fn get_game_paused(py: Python) -> PyResult<bool> {
+    Ok(default_viewer(py)?.get().get_game_paused(py))
+}
 
-    if let Some(game_state) = return_message.game_state {
-        GAME_STATE.with_borrow_mut(|state_cell| *state_cell = Some(game_state));
-    }
+/// Whether RLViser has responded in the last few seconds
+#[pyfunction]
+fn is_connected(py: Python) -> PyResult<bool> {
+    default_viewer(py)?.get().is_connected(py)
+}
 
-    match return_message.paused {
-        Some(paused) => {
-            GAME_PAUSED.with_borrow_mut(|paused_cell| *paused_cell = paused);
-            paused
-        }
-        None => GAME_PAUSED.with_borrow(|paused_cell| *paused_cell),
-    }
+/// Seconds since anything was received from RLViser, or `None` if nothing has been
+#[pyfunction]
+fn last_seen_seconds(py: Python) -> PyResult<Option<f64>> {
+    default_viewer(py)?.get().last_seen_seconds(py)
 }
 
+/// Set a function that's called with `True` when RLViser connects and `False` when it's lost,
+/// or `None` to remove it
 #[pyfunction]
-fn report_game_speed(speed: f32) {
-    socket::report_game_speed(speed).unwrap();
+fn on_connection_change(py: Python, callback: Option<Py<PyAny>>) -> PyResult<()> {
+    default_viewer(py)?.get().on_connection_change(py, callback);
+    Ok(())
 }
 
 #[pyfunction]
-fn report_game_paused(paused: bool) {
-    socket::report_game_paused(paused).unwrap();
+fn report_game_speed(py: Python, speed: f32) -> PyResult<()> {
+    default_viewer(py)?.get().report_game_speed(py, speed)
 }
 
-type Car = (u32, u8, CarConfig, CarState);
+#[pyfunction]
+fn report_game_paused(py: Python, paused: bool) -> PyResult<()> {
+    default_viewer(py)?.get().report_game_paused(py, paused)
+}
 
 #[pyfunction]
-fn launch() {
-    socket::launch().unwrap();
+#[pyo3(signature = (bind_addr=None, local_port=None, rlviser_host=None, rlviser_port=None, rlviser_path=None, rlviser_args=None, rlviser_env=None, rlviser_log_file=None))]
+#[allow(clippy::too_many_arguments)]
+fn launch(
+    py: Python,
+    bind_addr: Option<&str>,
+    local_port: Option<u16>,
+    rlviser_host: Option<String>,
+    rlviser_port: Option<u16>,
+    rlviser_path: Option<PathBuf>,
+    rlviser_args: Option<Vec<String>>,
+    rlviser_env: Option<HashMap<String, String>>,
+    rlviser_log_file: Option<PathBuf>,
+) -> PyResult<()> {
+    default_viewer(py)?.get().launch(
+        py,
+        bind_addr,
+        local_port,
+        rlviser_host,
+        rlviser_port,
+        rlviser_path,
+        rlviser_args,
+        rlviser_env,
+        rlviser_log_file,
+    )
 }
 
 #[pyfunction]
+#[pyo3(signature = (tick_count, tick_rate, game_mode, boost_pad_states, ball, cars, tiles=None, validation=None))]
+#[allow(clippy::too_many_arguments)]
 fn render(
+    py: Python,
     tick_count: u64,
     tick_rate: f32,
     game_mode: u8,
-    boost_pad_states: Vec<bool>,
+    boost_pad_states: Vec<BoostPadStateArg>,
     ball: BallState,
     cars: Vec<Car>,
-) {
-    let game_state = GameState {
+    tiles: Option<TTiles>,
+    validation: Option<&str>,
+) -> PyResult<()> {
+    default_viewer(py)?.get().render(
+        py,
         tick_count,
         tick_rate,
-        game_mode: if game_mode == 4 {
-            // python binds don't support dropshot yet
-            GameMode::TheVoid
-        } else {
-            GameMode::from_u8(game_mode)
-        },
+        game_mode,
+        boost_pad_states,
         ball,
-        pads: BOOST_PAD_LOCATIONS.with_borrow(|locs| {
-            locs.iter()
-                .zip(boost_pad_states)
-                .map(|(position, is_active)| BoostPad {
-                    position: *position,
-                    is_big: (position.z - 73.).abs() < f32::EPSILON,
-                    state: BoostPadState {
-                        is_active,
-                        ..Default::default()
-                    },
-                })
-                .collect()
-        }),
-        cars: cars
-            .into_iter()
-            .map(|(id, team, config, state)| CarInfo {
-                id,
-                team: Team::from_u8(team),
-                config,
-                state,
-            })
-            .collect(),
-    };
+        cars,
+        tiles,
+        validation,
+    )
+}
+
+/// Like `render`, but with the cars given as NumPy arrays with one row per car
+/// instead of reading every attribute of a `CarState`
+#[pyfunction]
+#[pyo3(signature = (tick_count, tick_rate, game_mode, boost_pad_states, ball, car_ids, car_teams, car_positions, car_rotations, car_velocities, car_ang_velocities, car_boost, car_flags, car_configs=None, tiles=None, validation=None))]
+#[allow(clippy::too_many_arguments)]
+fn render_arrays(
+    py: Python,
+    tick_count: u64,
+    tick_rate: f32,
+    game_mode: u8,
+    boost_pad_states: Vec<BoostPadStateArg>,
+    ball: BallState,
+    car_ids: Array1<u64>,
+    car_teams: Array1<u8>,
+    car_positions: Array2<f32>,
+    car_rotations: Array3<f32>,
+    car_velocities: Array2<f32>,
+    car_ang_velocities: Array2<f32>,
+    car_boost: Array1<f32>,
+    car_flags: Array2<bool>,
+    car_configs: Option<Vec<CarConfig>>,
+    tiles: Option<TTiles>,
+    validation: Option<&str>,
+) -> PyResult<()> {
+    default_viewer(py)?.get().render_arrays(
+        py,
+        tick_count,
+        tick_rate,
+        game_mode,
+        boost_pad_states,
+        ball,
+        car_ids,
+        car_teams,
+        car_positions,
+        car_rotations,
+        car_velocities,
+        car_ang_velocities,
+        car_boost,
+        car_flags,
+        car_configs,
+        tiles,
+        validation,
+    )
+}
 
-    socket::send_game_state(&game_state).unwrap();
+/// Render the current state of a RocketSim `Arena`
+///
+/// If no boost pad locations have been set, they're set from the arena's pads on first use
+#[pyfunction]
+#[pyo3(signature = (arena, tiles=None, validation=None))]
+fn render_arena(
+    py: Python,
+    arena: &Bound<PyAny>,
+    tiles: Option<TTiles>,
+    validation: Option<&str>,
+) -> PyResult<()> {
+    default_viewer(py)?
+        .get()
+        .render_arena(arena, tiles, validation)
+}
+
+/// Add a group of debug render commands to RLViser, replacing any existing group with the same id
+#[pyfunction]
+#[pyo3(signature = (group_id, commands, lifetime_seconds=None, lifetime_ticks=None))]
+fn add_render(
+    py: Python,
+    group_id: i32,
+    commands: Vec<RenderCommand>,
+    lifetime_seconds: Option<f32>,
+    lifetime_ticks: Option<u64>,
+) -> PyResult<()> {
+    default_viewer(py)?
+        .get()
+        .add_render(py, group_id, commands, lifetime_seconds, lifetime_ticks)
+}
+
+/// Remove a group of debug render commands from RLViser
+#[pyfunction]
+fn remove_render(py: Python, group_id: i32) -> PyResult<()> {
+    default_viewer(py)?.get().remove_render(py, group_id)
+}
+
+/// Remove every group of debug render commands this process added to RLViser
+#[pyfunction]
+fn clear_renders(py: Python) -> PyResult<()> {
+    default_viewer(py)?.get().clear_renders(py)
+}
+
+/// Whether the RLViser launched by this process is still running
+#[pyfunction]
+fn is_rlviser_running(py: Python) -> PyResult<bool> {
+    Ok(default_viewer(py)?.get().is_rlviser_running(py))
+}
+
+/// The process id of the RLViser launched by this process, or `None` if it hasn't been launched
+#[pyfunction]
+fn rlviser_pid(py: Python) -> PyResult<Option<u32>> {
+    Ok(default_viewer(py)?.get().rlviser_pid(py))
+}
+
+/// The exit code of the RLViser launched by this process,
+/// or `None` if it's still running, hasn't been launched or was killed by a signal
+#[pyfunction]
+fn rlviser_exit_code(py: Python) -> PyResult<Option<i32>> {
+    default_viewer(py)?.get().rlviser_exit_code(py)
+}
+
+/// Wait for the RLViser launched by this process to exit and return its exit code,
+/// or `None` if `timeout` seconds pass first
+#[pyfunction]
+#[pyo3(signature = (timeout=None))]
+fn wait_for_rlviser(py: Python, timeout: Option<f64>) -> PyResult<Option<i32>> {
+    default_viewer(py)?.get().wait_for_rlviser(py, timeout)
+}
+
+/// Send the quit signal to RLViser, optionally waiting for it to exit and killing it if it doesn't
+#[pyfunction]
+#[pyo3(signature = (timeout=None, force=false))]
+fn quit(py: Python, timeout: Option<f64>, force: bool) -> PyResult<bool> {
+    default_viewer(py)?.get().quit(py, timeout, force)
 }
 
-/// Send the quit signal to RLViser
+/// Close the RLViser launched by the default viewer when the interpreter exits
 #[pyfunction]
-fn quit() {
-    socket::quit().unwrap();
+fn quit_on_exit(py: Python) {
+    viewer::quit_default_viewer(py);
 }
@@ -0,0 +1,158 @@
+use pyo3::{prelude::*, sync::PyOnceLock, types::PyDict};
+use std::{
+    sync::{Mutex, PoisonError},
+    time::{Duration, Instant},
+};
+
+/// The Python logger for the diagnostics of this library
+static DIAGNOSTICS_LOGGER: Logger = Logger::new("rlviser_py");
+
+/// How long `debug_enabled` trusts its last answer, so a level change takes at most this long to apply
+const DEBUG_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// When `debug_enabled` last asked the diagnostics logger, and what it answered
+static DEBUG_ENABLED: Mutex<Option<(Instant, bool)>> = Mutex::new(None);
+
+/// The levels of Python's `logging` module
+#[derive(Clone, Copy, Debug)]
+pub enum Level {
+    Debug = 10,
+    Info = 20,
+    Warning = 30,
+}
+
+/// A value attached to a diagnostic, see `diagnostic`
+#[derive(Clone, Copy, Debug)]
+pub enum Field<'a> {
+    Int(usize),
+    Str(&'a str),
+}
+
+impl From<usize> for Field<'_> {
+    fn from(value: usize) -> Self {
+        Self::Int(value)
+    }
+}
+
+impl<'a> From<&'a str> for Field<'a> {
+    fn from(value: &'a str) -> Self {
+        Self::Str(value)
+    }
+}
+
+/// One of Python's loggers, looked up once on first use
+///
+/// `logging.getLogger` takes the logging module's lock, so it's kept out of the per-message path
+pub struct Logger {
+    name: &'static str,
+    logger: PyOnceLock<Py<PyAny>>,
+}
+
+impl Logger {
+    pub const fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            logger: PyOnceLock::new(),
+        }
+    }
+
+    fn get<'py>(&self, py: Python<'py>) -> PyResult<&Bound<'py, PyAny>> {
+        self.logger
+            .get_or_try_init(py, || {
+                py.import("logging")?
+                    .call_method1("getLogger", (self.name,))
+                    .map(Bound::unbind)
+            })
+            .map(|logger| logger.bind(py))
+    }
+
+    fn is_enabled_for(&self, py: Python, level: Level) -> PyResult<bool> {
+        self.get(py)?
+            .call_method1("isEnabledFor", (level as i32,))?
+            .is_truthy()
+    }
+}
+
+/// Log a message with one of Python's loggers
+///
+/// Safe to call from any thread, the message is dropped if Python is shutting down.
+/// Exceptions raised by handlers are reported by `logging` itself.
+pub fn log(logger: &Logger, level: Level, message: &str) {
+    Python::try_attach(|py| {
+        // this only fails while Python is shutting down and logging has been torn down
+        let _ = logger
+            .get(py)
+            .and_then(|logger| logger.call_method1("log", (level as i32, message)));
+    });
+}
+
+/// Whether the `rlviser_py` logger is enabled for `DEBUG`,
+/// for skipping the diagnostics logged for every message without calling into Python each time
+///
+/// The logger is only asked again once the last answer is `DEBUG_CHECK_INTERVAL` old.
+pub fn debug_enabled() -> bool {
+    let cached = *DEBUG_ENABLED.lock().unwrap_or_else(PoisonError::into_inner);
+    if let Some((checked_at, enabled)) = cached
+        && checked_at.elapsed() < DEBUG_CHECK_INTERVAL
+    {
+        return enabled;
+    }
+
+    // the lock isn't held while asking, since Python can switch threads during the call
+    let enabled = Python::try_attach(|py| {
+        DIAGNOSTICS_LOGGER
+            .is_enabled_for(py, Level::Debug)
+            .unwrap_or(false)
+    })
+    .unwrap_or(false);
+
+    *DEBUG_ENABLED.lock().unwrap_or_else(PoisonError::into_inner) = Some((Instant::now(), enabled));
+    enabled
+}
+
+/// Log a diagnostic with the `rlviser_py` logger
+///
+/// `kind` and `fields` are set as attributes of the log record (`record.kind`, `record.bytes`, ...)
+/// so handlers and filters can use them.
+/// The message is only formatted if the logger is enabled for `level`.
+pub fn diagnostic(
+    level: Level,
+    kind: &str,
+    fields: &[(&str, Field)],
+    message: impl FnOnce() -> String,
+) {
+    Python::try_attach(|py| {
+        // same as in `log`, diagnostics are dropped when logging isn't available
+        let _ = try_diagnostic(py, level, kind, fields, message);
+    });
+}
+
+fn try_diagnostic(
+    py: Python,
+    level: Level,
+    kind: &str,
+    fields: &[(&str, Field)],
+    message: impl FnOnce() -> String,
+) -> PyResult<()> {
+    if !DIAGNOSTICS_LOGGER.is_enabled_for(py, level)? {
+        return Ok(());
+    }
+
+    let extra = PyDict::new(py);
+    extra.set_item("kind", kind)?;
+
+    for &(name, value) in fields {
+        match value {
+            Field::Int(value) => extra.set_item(name, value)?,
+            Field::Str(value) => extra.set_item(name, value)?,
+        }
+    }
+
+    let kwargs = PyDict::new(py);
+    kwargs.set_item("extra", extra)?;
+    DIAGNOSTICS_LOGGER
+        .get(py)?
+        .call_method("log", (level as i32, message()), Some(&kwargs))?;
+
+    Ok(())
+}
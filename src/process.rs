@@ -0,0 +1,237 @@
+use pyo3::prelude::*;
+use std::{
+    collections::HashMap,
+    env,
+    fs::File,
+    io::{self, BufRead, BufReader, Read},
+    path::{Path, PathBuf},
+    process::{Child, Command, ExitStatus, Stdio},
+    sync::{Mutex, MutexGuard, PoisonError},
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    error::{Error, Result},
+    logging::{self, Level, Logger},
+};
+
+const RLVISER_EXE: &str = if cfg!(windows) {
+    "rlviser.exe"
+} else {
+    "rlviser"
+};
+
+const RLVISER_PATH_ENV: &str = "RLVISER_PATH";
+
+/// The Python logger that RLViser's output is forwarded to
+static OUTPUT_LOGGER: Logger = Logger::new("rlviser");
+
+/// How often the process is checked while waiting for it to exit
+const WAIT_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Which RLViser executable to start and how
+#[derive(Clone, Debug, Default)]
+pub struct LaunchConfig {
+    /// Searched for when `None`, see `resolve_path`
+    pub path: Option<PathBuf>,
+    pub args: Vec<String>,
+    /// Set on top of the environment inherited from this process
+    pub env: HashMap<String, String>,
+    /// Where RLViser's stdout and stderr are appended, instead of the `rlviser` logger
+    pub log_file: Option<PathBuf>,
+}
+
+/// The directory `rlviser_py` was imported from, where RLViser can be installed alongside it
+fn package_dir() -> Option<PathBuf> {
+    Python::attach(|py| {
+        let file: PathBuf = py
+            .import("rlviser_py")
+            .and_then(|module| module.getattr("__file__"))
+            .and_then(|file| file.extract())
+            .ok()?;
+
+        file.parent().map(Path::to_path_buf)
+    })
+}
+
+impl LaunchConfig {
+    /// The default config, with `RLVISER_PATH` applied on top
+    pub fn from_env() -> Self {
+        Self {
+            path: env::var_os(RLVISER_PATH_ENV).map(PathBuf::from),
+            ..Default::default()
+        }
+    }
+
+    /// Replace the parts of the config that were given
+    pub fn with_overrides(
+        mut self,
+        path: Option<PathBuf>,
+        args: Option<Vec<String>>,
+        env: Option<HashMap<String, String>>,
+        log_file: Option<PathBuf>,
+    ) -> Self {
+        if let Some(path) = path {
+            self.path = Some(path);
+        }
+
+        if let Some(args) = args {
+            self.args = args;
+        }
+
+        if let Some(env) = env {
+            self.env = env;
+        }
+
+        if let Some(log_file) = log_file {
+            self.log_file = Some(log_file);
+        }
+
+        self
+    }
+
+    /// The executable to start: the configured path if there is one,
+    /// otherwise the first RLViser found in the working directory, the package directory or `PATH`
+    fn resolve_path(&self) -> Result<PathBuf> {
+        if let Some(path) = &self.path {
+            return Ok(path.clone());
+        }
+
+        let search_path = env::var_os("PATH").unwrap_or_default();
+
+        [PathBuf::from("."), package_dir().unwrap_or_default()]
+            .into_iter()
+            .filter(|dir| !dir.as_os_str().is_empty())
+            .chain(env::split_paths(&search_path))
+            .map(|dir| dir.join(RLVISER_EXE))
+            .find(|path| path.is_file())
+            .ok_or_else(|| {
+                Error::Launch(
+                    RLVISER_EXE.to_string(),
+                    io::Error::new(
+                        io::ErrorKind::NotFound,
+                        format!(
+                            "not found in the working directory, the rlviser_py package directory or PATH, set {RLVISER_PATH_ENV} to its location"
+                        ),
+                    ),
+                )
+            })
+    }
+}
+
+/// Forward each line of RLViser's output to the `rlviser` logger until the pipe is closed
+fn forward_output(name: &str, output: impl Read + Send + 'static, level: Level) -> io::Result<()> {
+    thread::Builder::new()
+        .name(format!("rlviser-{name}"))
+        .spawn(move || {
+            let mut reader = BufReader::new(output);
+            let mut line = Vec::new();
+
+            while matches!(reader.read_until(b'\n', &mut line), Ok(n) if n != 0) {
+                logging::log(
+                    &OUTPUT_LOGGER,
+                    level,
+                    String::from_utf8_lossy(&line).trim_end(),
+                );
+                line.clear();
+            }
+        })?;
+
+    Ok(())
+}
+
+/// An instance of RLViser that was launched by us
+///
+/// The child is only locked for a moment at a time, so the process can be waited on from one thread
+/// while others are checking on it
+pub struct ViewerProcess {
+    child: Mutex<Child>,
+}
+
+impl ViewerProcess {
+    pub fn spawn(config: &LaunchConfig) -> Result<Self> {
+        let path = config.resolve_path()?;
+        let launch_error = |e| Error::Launch(path.display().to_string(), e);
+
+        let mut command = Command::new(&path);
+        command.args(&config.args).envs(&config.env);
+
+        if let Some(log_file) = &config.log_file {
+            let file = File::options()
+                .create(true)
+                .append(true)
+                .open(log_file)
+                .map_err(|e| {
+                    launch_error(io::Error::new(
+                        e.kind(),
+                        format!("couldn't open log file {}: {e}", log_file.display()),
+                    ))
+                })?;
+
+            command
+                .stdout(file.try_clone().map_err(launch_error)?)
+                .stderr(file);
+        } else {
+            command.stdout(Stdio::piped()).stderr(Stdio::piped());
+        }
+
+        let mut child = command.spawn().map_err(launch_error)?;
+
+        if let Some(stdout) = child.stdout.take() {
+            forward_output("stdout", stdout, Level::Info).map_err(launch_error)?;
+        }
+
+        if let Some(stderr) = child.stderr.take() {
+            forward_output("stderr", stderr, Level::Warning).map_err(launch_error)?;
+        }
+
+        Ok(Self {
+            child: Mutex::new(child),
+        })
+    }
+
+    fn child(&self) -> MutexGuard<'_, Child> {
+        self.child.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub fn id(&self) -> u32 {
+        self.child().id()
+    }
+
+    /// The exit status of the process, or `None` if it's still running
+    pub fn exit_status(&self) -> io::Result<Option<ExitStatus>> {
+        self.child().try_wait()
+    }
+
+    pub fn is_running(&self) -> bool {
+        matches!(self.exit_status(), Ok(None))
+    }
+
+    /// Wait for the process to exit, giving up after `timeout` if there is one
+    pub fn wait(&self, timeout: Option<Duration>) -> io::Result<Option<ExitStatus>> {
+        let start = Instant::now();
+        loop {
+            if let Some(exit_status) = self.exit_status()? {
+                return Ok(Some(exit_status));
+            }
+
+            if timeout.is_some_and(|timeout| start.elapsed() >= timeout) {
+                return Ok(None);
+            }
+
+            thread::sleep(WAIT_POLL_INTERVAL);
+        }
+    }
+
+    /// Kill the process if it's still running and wait for it to exit
+    pub fn kill(&self) -> io::Result<()> {
+        let mut child = self.child();
+        if child.try_wait()?.is_none() {
+            child.kill()?;
+            child.wait()?;
+        }
+
+        Ok(())
+    }
+}
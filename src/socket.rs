@@ -1,31 +1,170 @@
-use crate::bytes::{FromFlat, GameState, ToFlat};
+use crate::{
+    bytes::{FromFlat, GameState, ToFlat},
+    draw::RenderGroup,
+    error::{Error, Result},
+    fragment::{self, MAX_DATAGRAM_SIZE, Reassembler},
+    logging::{self, Level},
+};
 use std::{
-    io,
-    net::{Ipv4Addr, SocketAddr, UdpSocket},
-    process::Command,
-    sync::OnceLock,
+    env, fmt, io,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, ToSocketAddrs, UdpSocket},
+    str::FromStr,
+    sync::{
+        Mutex, PoisonError,
+        atomic::{AtomicU32, Ordering},
+    },
 };
-use sysinfo::{ProcessRefreshKind, RefreshKind, System};
 
 use crate::flat::rocketsim as fb;
 
 const RLVISER_PORT: u16 = 45243;
 const ROCKETSIM_PORT: u16 = 34254;
+const RLVISER_HOST: &str = "127.0.0.1";
+const BIND_ADDR: IpAddr = IpAddr::V4(Ipv4Addr::UNSPECIFIED);
 const PACKET_SIZE_BYTES: usize = 8;
+/// Big enough for any UDP datagram
+const MAX_RECV_SIZE: usize = u16::MAX as usize;
+
+const BIND_ADDR_ENV: &str = "RLVISER_BIND_ADDR";
+const LOCAL_PORT_ENV: &str = "RLVISER_LOCAL_PORT";
+const RLVISER_HOST_ENV: &str = "RLVISER_HOST";
+const RLVISER_PORT_ENV: &str = "RLVISER_PORT";
+
+/// Where the socket binds and where RLViser is listening
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SocketConfig {
+    pub bind_addr: IpAddr,
+    /// 0 lets the OS pick a free port
+    pub local_port: u16,
+    pub rlviser_host: String,
+    pub rlviser_port: u16,
+}
 
-const RLVISER_PATH: &str = if cfg!(windows) {
-    "./rlviser.exe"
-} else {
-    "./rlviser"
-};
+/// Read and parse an environment variable, if it's set
+fn parse_env<T: FromStr>(name: &str) -> io::Result<Option<T>>
+where
+    T::Err: fmt::Display,
+{
+    let Ok(value) = env::var(name) else {
+        return Ok(None);
+    };
+
+    value.parse().map(Some).map_err(|e| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Invalid value for {name} ({value}): {e}"),
+        )
+    })
+}
+
+fn parse_bind_addr(bind_addr: &str) -> io::Result<IpAddr> {
+    bind_addr.parse().map_err(|e| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Invalid bind address ({bind_addr}): {e}"),
+        )
+    })
+}
+
+impl SocketConfig {
+    /// The default config, with any of the `RLVISER_*` environment variables applied on top
+    pub fn from_env() -> Result<Self> {
+        Ok(Self {
+            bind_addr: parse_env(BIND_ADDR_ENV)
+                .map_err(Error::Bind)?
+                .unwrap_or(BIND_ADDR),
+            local_port: parse_env(LOCAL_PORT_ENV)
+                .map_err(Error::Bind)?
+                .unwrap_or(ROCKETSIM_PORT),
+            rlviser_host: parse_env(RLVISER_HOST_ENV)
+                .map_err(Error::Bind)?
+                .unwrap_or_else(|| RLVISER_HOST.to_string()),
+            rlviser_port: parse_env(RLVISER_PORT_ENV)
+                .map_err(Error::Bind)?
+                .unwrap_or(RLVISER_PORT),
+        })
+    }
+
+    /// Replace the parts of the config that were given
+    pub fn with_overrides(
+        mut self,
+        bind_addr: Option<&str>,
+        local_port: Option<u16>,
+        rlviser_host: Option<String>,
+        rlviser_port: Option<u16>,
+    ) -> Result<Self> {
+        if let Some(bind_addr) = bind_addr {
+            self.bind_addr = parse_bind_addr(bind_addr).map_err(Error::Bind)?;
+        }
+
+        if let Some(local_port) = local_port {
+            self.local_port = local_port;
+        }
+
+        if let Some(rlviser_host) = rlviser_host {
+            self.rlviser_host = rlviser_host;
+        }
+
+        if let Some(rlviser_port) = rlviser_port {
+            self.rlviser_port = rlviser_port;
+        }
 
-static SOCKET: OnceLock<SocketHandler> = OnceLock::new();
+        Ok(self)
+    }
+
+    /// Resolve the RLViser host to an address the bound socket can send to
+    fn resolve_rlviser_addr(&self) -> io::Result<SocketAddr> {
+        (self.rlviser_host.as_str(), self.rlviser_port)
+            .to_socket_addrs()?
+            .find(|addr| addr.is_ipv4() == self.bind_addr.is_ipv4())
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::AddrNotAvailable,
+                    format!(
+                        "{}:{} has no address reachable from {}",
+                        self.rlviser_host, self.rlviser_port, self.bind_addr
+                    ),
+                )
+            })
+    }
+
+    /// Whether RLViser is expected to run on this machine,
+    /// which is when its address is loopback or any other address we can bind to
+    pub fn rlviser_is_local(&self) -> bool {
+        let Ok(rlviser_addr) = self.resolve_rlviser_addr() else {
+            return false;
+        };
+
+        let ip = rlviser_addr.ip();
+        ip.is_loopback() || ip.is_unspecified() || UdpSocket::bind((ip, 0)).is_ok()
+    }
+
+    /// Whether something on this machine is already bound to RLViser's port,
+    /// which is most likely an RLViser that was started some other way
+    pub fn rlviser_is_listening(&self) -> bool {
+        let Ok(rlviser_addr) = self.resolve_rlviser_addr() else {
+            return false;
+        };
+
+        // binding to every address fails if the port is taken on any of them
+        let any_ip = match rlviser_addr {
+            SocketAddr::V4(_) => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            SocketAddr::V6(_) => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+        };
+
+        UdpSocket::bind((any_ip, rlviser_addr.port()))
+            .is_err_and(|e| e.kind() == io::ErrorKind::AddrInUse)
+    }
+}
 
 #[derive(Default)]
 pub struct ReturnMessage {
     pub game_state: Option<GameState>,
     pub speed: Option<f32>,
     pub paused: Option<bool>,
+    pub heard_from_rlviser: bool,
+    /// RLViser sent the `Connection` handshake, which it does when it starts or gets ours
+    pub handshake: bool,
 }
 
 impl ReturnMessage {
@@ -33,12 +172,16 @@ impl ReturnMessage {
         game_state: None,
         speed: None,
         paused: None,
+        heard_from_rlviser: false,
+        handshake: false,
     };
 }
 
-struct SocketHandler {
+pub struct SocketHandler {
     socket: UdpSocket,
     rlviser_addr: SocketAddr,
+    next_message_id: AtomicU32,
+    reassembler: Mutex<Reassembler>,
 }
 
 /// Encode a flatbuffer Message into the wire format:
@@ -55,6 +198,16 @@ fn encode_message(message: fb::Message) -> Vec<u8> {
     buffer
 }
 
+/// Warn about a message from RLViser that couldn't be decoded and was dropped
+fn log_decode_error(src: SocketAddr, bytes: usize, e: &io::Error) {
+    logging::diagnostic(
+        Level::Warning,
+        "decode_error",
+        &[("bytes", bytes.into())],
+        || format!("Dropped a malformed message of {bytes} bytes from {src}: {e}"),
+    );
+}
+
 /// Decode a flatbuffer Packet payload (after the 8-byte header) into a Message.
 fn decode_payload(payload: &[u8]) -> io::Result<fb::Message> {
     let packet: fb::Packet = <fb::PacketRef<'_> as planus::ReadAsRoot>::read_as_root(payload)
@@ -63,57 +216,195 @@ fn decode_payload(payload: &[u8]) -> io::Result<fb::Message> {
     Ok(packet.message)
 }
 
-impl SocketHandler {
-    pub fn new() -> io::Result<Self> {
-        let sys = System::new_with_specifics(
-            RefreshKind::nothing().with_processes(ProcessRefreshKind::nothing()),
-        );
-        let rlviser_procs = sys.processes_by_exact_name("rlviser".as_ref()).count();
-
-        // launch RLViser if it hasn't been already
-        if rlviser_procs == 0
-            && let Err(e) = Command::new(RLVISER_PATH).spawn()
-        {
-            eprintln!("Failed to launch RLViser ({RLVISER_PATH}): {e}");
-        }
+/// The name of a message's type, for diagnostics
+fn message_type(message: &fb::Message) -> &'static str {
+    match message {
+        fb::Message::Connection(_) => "Connection",
+        fb::Message::Quit(_) => "Quit",
+        fb::Message::Speed(_) => "Speed",
+        fb::Message::Paused(_) => "Paused",
+        fb::Message::GameState(_) => "GameState",
+        fb::Message::AddRender(_) => "AddRender",
+        fb::Message::RemoveRender(_) => "RemoveRender",
+        fb::Message::ClearRenders(_) => "ClearRenders",
+        fb::Message::Fragment(_) => "Fragment",
+        fb::Message::Heartbeat(_) => "Heartbeat",
+    }
+}
 
-        let socket = UdpSocket::bind((Ipv4Addr::new(0, 0, 0, 0), ROCKETSIM_PORT))?;
-        let rlviser_addr = (Ipv4Addr::new(127, 0, 0, 1), RLVISER_PORT).into();
+impl SocketHandler {
+    pub fn new(config: &SocketConfig) -> Result<Self> {
+        let socket = UdpSocket::bind((config.bind_addr, config.local_port)).map_err(Error::Bind)?;
+        let rlviser_addr = config.resolve_rlviser_addr().map_err(Error::Bind)?;
 
-        socket.send_to(
-            &encode_message(fb::Message::Connection(Box::default())),
-            rlviser_addr,
-        )?;
-        socket.set_nonblocking(true)?;
+        socket.set_nonblocking(true).map_err(Error::Bind)?;
 
-        Ok(Self {
+        let socket_handler = Self {
             socket,
             rlviser_addr,
-        })
+            next_message_id: AtomicU32::new(0),
+            reassembler: Mutex::default(),
+        };
+
+        socket_handler.send_connection()?;
+        Ok(socket_handler)
     }
 
-    fn handle_return_messages(&self) -> io::Result<ReturnMessage> {
+    /// Send a message to RLViser, splitting it into fragments if it doesn't fit in one datagram
+    fn send_message(&self, message: fb::Message) -> Result<()> {
+        let message_type = message_type(&message);
+        let bytes = encode_message(message);
+        if bytes.len() <= MAX_DATAGRAM_SIZE {
+            self.socket
+                .send_to(&bytes, self.rlviser_addr)
+                .map_err(Error::Send)?;
+
+            // this runs for every message, so only call into Python when it'll be logged
+            if logging::debug_enabled() {
+                logging::diagnostic(
+                    Level::Debug,
+                    "sent",
+                    &[
+                        ("message_type", message_type.into()),
+                        ("bytes", bytes.len().into()),
+                    ],
+                    || {
+                        format!(
+                            "Sent {message_type} ({} bytes) to {}",
+                            bytes.len(),
+                            self.rlviser_addr
+                        )
+                    },
+                );
+            }
+
+            return Ok(());
+        }
+
+        let message_id = self.next_message_id.fetch_add(1, Ordering::Relaxed);
+        let fragments = fragment::split(message_id, &bytes[PACKET_SIZE_BYTES..])?;
+        let fragment_count = fragments.len();
+
+        for fragment in fragments {
+            let bytes = encode_message(fb::Message::Fragment(Box::new(fragment)));
+            self.socket
+                .send_to(&bytes, self.rlviser_addr)
+                .map_err(Error::Send)?;
+        }
+
+        if logging::debug_enabled() {
+            logging::diagnostic(
+                Level::Debug,
+                "sent",
+                &[
+                    ("message_type", message_type.into()),
+                    ("bytes", bytes.len().into()),
+                    ("fragments", fragment_count.into()),
+                ],
+                || {
+                    format!(
+                        "Sent {message_type} ({} bytes) to {} in {fragment_count} fragments",
+                        bytes.len(),
+                        self.rlviser_addr
+                    )
+                },
+            );
+        }
+
+        Ok(())
+    }
+
+    pub fn handle_return_messages(&self) -> io::Result<ReturnMessage> {
         let mut header = [0u8; PACKET_SIZE_BYTES];
-        let mut buffer = Vec::with_capacity(1024);
+        let mut buffer = vec![0; MAX_RECV_SIZE];
 
         let mut game_state = None;
         let mut speed = None;
         let mut paused = None;
+        let mut heard_from_rlviser = false;
+        let mut handshake = false;
 
-        while self.socket.peek_from(&mut header).is_ok() {
-            let packet_size = PACKET_SIZE_BYTES + u64::from_be_bytes(header) as usize;
-            buffer.resize(packet_size, 0);
+        let mut reassembler = self
+            .reassembler
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        reassembler.remove_expired();
 
-            let (_, _src) = self.socket.recv_from(&mut buffer)?;
-            let payload = &buffer[PACKET_SIZE_BYTES..];
-
-            let Ok(message) = decode_payload(payload) else {
+        while self.socket.peek_from(&mut header).is_ok() {
+            let (received, src) = self.socket.recv_from(&mut buffer)?;
+            let payload_size = u64::from_be_bytes(header);
+
+            let Some(payload) = buffer[..received]
+                .get(PACKET_SIZE_BYTES..)
+                .filter(|payload| payload.len() as u64 == payload_size)
+            else {
+                let e = io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("the header says the payload is {payload_size} bytes"),
+                );
+                log_decode_error(src, received, &e);
                 continue;
             };
 
+            let mut message = match decode_payload(payload) {
+                Ok(message) => message,
+                Err(e) => {
+                    log_decode_error(src, payload.len(), &e);
+                    continue;
+                }
+            };
+
+            if let fb::Message::Fragment(fragment) = message {
+                let (message_id, index, count) =
+                    (fragment.message_id, fragment.index, fragment.count);
+                let Some(payload) = reassembler.insert(src, *fragment) else {
+                    logging::diagnostic(
+                        Level::Debug,
+                        "fragment",
+                        &[
+                            ("message_id", (message_id as usize).into()),
+                            ("index", usize::from(index).into()),
+                            ("count", usize::from(count).into()),
+                        ],
+                        || {
+                            format!(
+                                "Received fragment {index}/{count} of message {message_id} from {src}"
+                            )
+                        },
+                    );
+                    continue;
+                };
+
+                message = match decode_payload(&payload) {
+                    Ok(message) => message,
+                    Err(e) => {
+                        log_decode_error(src, payload.len(), &e);
+                        continue;
+                    }
+                };
+            }
+
+            heard_from_rlviser = true;
+
+            let message_type = message_type(&message);
+            if logging::debug_enabled() {
+                logging::diagnostic(
+                    Level::Debug,
+                    "received",
+                    &[
+                        ("message_type", message_type.into()),
+                        ("bytes", received.into()),
+                    ],
+                    || format!("Received {message_type} ({received} bytes) from {src}"),
+                );
+            }
+
             match message {
                 fb::Message::Connection(_) => {
-                    eprintln!("Connection established");
+                    logging::diagnostic(Level::Info, "connection", &[], || {
+                        format!("Connection established with RLViser at {src}")
+                    });
+                    handshake = true;
                 }
                 fb::Message::Speed(s) => {
                     speed = Some(s.speed);
@@ -124,8 +415,11 @@ impl SocketHandler {
                 fb::Message::GameState(gs) => {
                     game_state = Some(GameState::from_flat(*gs));
                 }
-                fb::Message::Quit(_) => {}
-                fb::Message::AddRender(_) | fb::Message::RemoveRender(_) => {}
+                fb::Message::Quit(_) | fb::Message::Heartbeat(_) => {}
+                fb::Message::AddRender(_)
+                | fb::Message::RemoveRender(_)
+                | fb::Message::ClearRenders(_)
+                | fb::Message::Fragment(_) => {}
             }
         }
 
@@ -133,72 +427,52 @@ impl SocketHandler {
             game_state,
             speed,
             paused,
+            heard_from_rlviser,
+            handshake,
         })
     }
 
-    fn send_game_state(&self, game_state: &GameState) -> io::Result<()> {
+    pub fn send_game_state(&self, game_state: &GameState) -> Result<()> {
         let fb_gs = game_state.to_flat();
-        let bytes = encode_message(fb::Message::GameState(Box::new(fb_gs)));
-        self.socket.send_to(&bytes, self.rlviser_addr)?;
-        Ok(())
+        self.send_message(fb::Message::GameState(Box::new(fb_gs)))
     }
 
-    fn report_game_speed(&self, speed: f32) -> io::Result<()> {
-        let bytes = encode_message(fb::Message::Speed(Box::new(fb::Speed { speed })));
-        self.socket.send_to(&bytes, self.rlviser_addr)?;
-        Ok(())
+    pub fn report_game_speed(&self, speed: f32) -> Result<()> {
+        self.send_message(fb::Message::Speed(Box::new(fb::Speed { speed })))
     }
 
-    fn report_game_paused(&self, paused: bool) -> io::Result<()> {
-        let bytes = encode_message(fb::Message::Paused(Box::new(fb::Paused { paused })));
-        self.socket.send_to(&bytes, self.rlviser_addr)?;
-        Ok(())
+    pub fn report_game_paused(&self, paused: bool) -> Result<()> {
+        self.send_message(fb::Message::Paused(Box::new(fb::Paused { paused })))
     }
 
-    fn send_quit(&self) -> io::Result<()> {
-        let bytes = encode_message(fb::Message::Quit(Box::default()));
-        self.socket.send_to(&bytes, self.rlviser_addr)?;
-        Ok(())
+    pub fn add_render(&self, group: &RenderGroup) -> Result<()> {
+        self.send_message(fb::Message::AddRender(Box::new(group.to_flat())))
     }
-}
 
-pub fn get_return_messages() -> ReturnMessage {
-    let Some(socket_handler) = SOCKET.get() else {
-        return ReturnMessage::NONE;
-    };
-
-    socket_handler
-        .handle_return_messages()
-        .unwrap_or(ReturnMessage::NONE)
-}
-
-pub fn send_game_state(game_state: &GameState) -> io::Result<()> {
-    let socket_handler = SOCKET.get_or_init(|| SocketHandler::new().unwrap());
-    socket_handler.send_game_state(game_state)
-}
+    pub fn remove_render(&self, id: i32) -> Result<()> {
+        self.send_message(fb::Message::RemoveRender(Box::new(fb::RemoveRender { id })))
+    }
 
-pub fn report_game_speed(speed: f32) -> io::Result<()> {
-    let socket_handler = SOCKET.get_or_init(|| SocketHandler::new().unwrap());
-    socket_handler.report_game_speed(speed)
-}
+    pub fn clear_renders(&self) -> Result<()> {
+        self.send_message(fb::Message::ClearRenders(Box::default()))
+    }
 
-pub fn report_game_paused(paused: bool) -> io::Result<()> {
-    let socket_handler = SOCKET.get_or_init(|| SocketHandler::new().unwrap());
-    socket_handler.report_game_paused(paused)
-}
+    pub fn send_connection(&self) -> Result<()> {
+        self.send_message(fb::Message::Connection(Box::default()))
+    }
 
-pub fn launch() -> io::Result<()> {
-    if let Err(e) = Command::new(RLVISER_PATH).spawn() {
-        eprintln!("Failed to launch RLViser ({RLVISER_PATH}): {e}");
+    pub fn send_heartbeat(&self) -> Result<()> {
+        self.send_message(fb::Message::Heartbeat(Box::default()))
     }
 
-    Ok(())
+    pub fn send_quit(&self) -> Result<()> {
+        self.send_message(fb::Message::Quit(Box::default()))
+    }
 }
 
-pub fn quit() -> io::Result<()> {
-    if let Some(socket_handler) = SOCKET.get() {
-        socket_handler.send_quit()?;
+impl Drop for SocketHandler {
+    fn drop(&mut self) {
+        // don't leave our debug renders in RLViser after we're gone
+        let _ = self.clear_renders();
     }
-
-    Ok(())
 }
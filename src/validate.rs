@@ -0,0 +1,522 @@
+use pyo3::{exceptions::PyValueError, prelude::*};
+
+use crate::{
+    bytes::{GameMode, GameState, RotMat, Vec3},
+    logging::{self, Level},
+};
+
+/// How far off a rotation matrix's rows can be from unit length and from being perpendicular
+const ORTHONORMAL_TOLERANCE: f32 = 1e-3;
+/// How far past the arena's walls, floor and ceiling an object can be, since they're only approximate
+const BOUNDS_LEEWAY: f32 = 200.;
+const MAX_BOOST: f32 = 100.;
+
+/// What `render` does with game states that fail the sanity checks
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ValidationMode {
+    #[default]
+    Off,
+    /// Log a warning and send the state as-is
+    Warn,
+    /// Raise a `ValueError` and don't send the state
+    Raise,
+    /// Fix the invalid values and send the fixed state
+    Repair,
+}
+
+impl ValidationMode {
+    pub fn parse(mode: &str) -> PyResult<Self> {
+        match mode {
+            "off" => Ok(Self::Off),
+            "warn" => Ok(Self::Warn),
+            "raise" => Ok(Self::Raise),
+            "repair" => Ok(Self::Repair),
+            _ => Err(PyValueError::new_err(format!(
+                "Invalid validation mode {mode:?}, expected \"off\", \"warn\", \"raise\" or \"repair\""
+            ))),
+        }
+    }
+}
+
+/// Half the width and length of an arena's bounding box and its height, including the goals
+struct ArenaBounds {
+    extent_x: f32,
+    extent_y: f32,
+    height: f32,
+}
+
+impl ArenaBounds {
+    const fn of(game_mode: GameMode) -> Option<Self> {
+        match game_mode {
+            GameMode::Soccar | GameMode::HeatSeeker | GameMode::Snowday => Some(Self {
+                extent_x: 4096.,
+                extent_y: 6000.,
+                height: 2044.,
+            }),
+            GameMode::Hoops => Some(Self {
+                extent_x: 2966.67,
+                extent_y: 3581.,
+                height: 1820.,
+            }),
+            GameMode::Dropshot => Some(Self {
+                extent_x: 5026.,
+                extent_y: 4555.,
+                height: 2024.,
+            }),
+            GameMode::TheVoid => None,
+        }
+    }
+
+    fn contains(&self, pos: Vec3) -> bool {
+        pos.x.abs() <= self.extent_x + BOUNDS_LEEWAY
+            && pos.y.abs() <= self.extent_y + BOUNDS_LEEWAY
+            && (-BOUNDS_LEEWAY..=self.height + BOUNDS_LEEWAY).contains(&pos.z)
+    }
+
+    fn clamp(&self, pos: Vec3) -> Vec3 {
+        Vec3::new(
+            pos.x.clamp(-self.extent_x, self.extent_x),
+            pos.y.clamp(-self.extent_y, self.extent_y),
+            pos.z.clamp(0., self.height),
+        )
+    }
+}
+
+fn is_orthonormal(rot_mat: &RotMat) -> bool {
+    let RotMat { forward, right, up } = *rot_mat;
+
+    [forward, right, up]
+        .into_iter()
+        .all(|row| (row.length() - 1.).abs() <= ORTHONORMAL_TOLERANCE)
+        && [forward.dot(right), forward.dot(up), right.dot(up)]
+            .into_iter()
+            .all(|dot| dot.abs() <= ORTHONORMAL_TOLERANCE)
+}
+
+/// The closest orthonormal matrix to `rot_mat` by Gram-Schmidt, keeping the direction of `forward`,
+/// or the identity if it's too degenerate for that
+fn orthonormalize(rot_mat: &RotMat) -> RotMat {
+    let forward_length = rot_mat.forward.length();
+    if !rot_mat.forward.is_finite() || forward_length <= f32::EPSILON {
+        return RotMat::IDENTITY;
+    }
+
+    let forward = rot_mat.forward.scale(1. / forward_length);
+    let right = rot_mat.right.sub(forward.scale(forward.dot(rot_mat.right)));
+    let right_length = right.length();
+    if !right.is_finite() || right_length <= f32::EPSILON {
+        return RotMat::IDENTITY;
+    }
+
+    let right = right.scale(1. / right_length);
+    RotMat::new(forward, right, forward.cross(right))
+}
+
+/// Collects the problems with a game state, fixing each one as it's found if `repair` is set
+struct Checker {
+    repair: bool,
+    problems: Vec<String>,
+}
+
+impl Checker {
+    fn vector(&mut self, object: &str, field: &str, value: &mut Vec3) {
+        if value.is_finite() {
+            return;
+        }
+
+        self.problems.push(format!(
+            "{object} {field} {:?} isn't finite",
+            value.to_array()
+        ));
+
+        if self.repair {
+            *value = Vec3::ZERO;
+        }
+    }
+
+    fn rot_mat(&mut self, object: &str, rot_mat: &mut RotMat) {
+        if is_orthonormal(rot_mat) {
+            return;
+        }
+
+        self.problems.push(format!(
+            "{object} rot_mat {:?} isn't orthonormal",
+            rot_mat.to_array()
+        ));
+
+        if self.repair {
+            *rot_mat = orthonormalize(rot_mat);
+        }
+    }
+
+    fn bounds(&mut self, object: &str, bounds: Option<&ArenaBounds>, pos: &mut Vec3) {
+        let Some(bounds) = bounds else {
+            return;
+        };
+
+        if !pos.is_finite() || bounds.contains(*pos) {
+            return;
+        }
+
+        self.problems.push(format!(
+            "{object} pos {:?} is outside of the arena",
+            pos.to_array()
+        ));
+
+        if self.repair {
+            *pos = bounds.clamp(*pos);
+        }
+    }
+
+    fn boost(&mut self, object: &str, boost: &mut f32) {
+        if (0. ..=MAX_BOOST).contains(boost) {
+            return;
+        }
+
+        self.problems.push(format!(
+            "{object} boost {boost} isn't between 0 and {MAX_BOOST}"
+        ));
+
+        if self.repair {
+            *boost = if boost.is_nan() {
+                0.
+            } else {
+                boost.clamp(0., MAX_BOOST)
+            };
+        }
+    }
+}
+
+/// Run the sanity checks on a game state that's about to be sent,
+/// then warn, raise or repair depending on `mode`
+pub fn validate(game_state: &mut GameState, mode: ValidationMode) -> PyResult<()> {
+    if mode == ValidationMode::Off {
+        return Ok(());
+    }
+
+    let mut checker = Checker {
+        repair: mode == ValidationMode::Repair,
+        problems: Vec::new(),
+    };
+    let bounds = ArenaBounds::of(game_state.game_mode);
+
+    let ball = &mut game_state.ball;
+    checker.vector("ball", "pos", &mut ball.pos);
+    checker.vector("ball", "vel", &mut ball.vel);
+    checker.vector("ball", "ang_vel", &mut ball.ang_vel);
+    checker.rot_mat("ball", &mut ball.rot_mat);
+    checker.bounds("ball", bounds.as_ref(), &mut ball.pos);
+
+    for (i, car) in game_state.cars.iter_mut().enumerate() {
+        let object = format!("car {i} (id {})", car.id);
+        let state = &mut car.state;
+
+        checker.vector(&object, "pos", &mut state.pos);
+        checker.vector(&object, "vel", &mut state.vel);
+        checker.vector(&object, "ang_vel", &mut state.ang_vel);
+        checker.rot_mat(&object, &mut state.rot_mat);
+        checker.boost(&object, &mut state.boost);
+
+        // demoed cars can be anywhere until they respawn
+        if !state.is_demoed {
+            checker.bounds(&object, bounds.as_ref(), &mut state.pos);
+        }
+    }
+
+    if checker.problems.is_empty() {
+        return Ok(());
+    }
+
+    let problem_count = checker.problems.len();
+    let problems = checker.problems.join("; ");
+
+    match mode {
+        ValidationMode::Off => {}
+        ValidationMode::Warn => {
+            logging::diagnostic(
+                Level::Warning,
+                "validation",
+                &[("problems", problem_count.into())],
+                || {
+                    format!(
+                        "Invalid game state at tick {}: {problems}",
+                        game_state.tick_count
+                    )
+                },
+            );
+        }
+        ValidationMode::Raise => {
+            return Err(PyValueError::new_err(format!(
+                "Invalid game state: {problems}"
+            )));
+        }
+        ValidationMode::Repair => {
+            logging::diagnostic(
+                Level::Debug,
+                "validation",
+                &[("problems", problem_count.into())],
+                || {
+                    format!(
+                        "Repaired the game state at tick {}: {problems}",
+                        game_state.tick_count
+                    )
+                },
+            );
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bytes::{BallState, CarInfo, CarState};
+
+    fn assert_close(a: Vec3, b: Vec3) {
+        assert!(a.sub(b).length() <= ORTHONORMAL_TOLERANCE, "{a:?} != {b:?}");
+    }
+
+    fn assert_identity(rot_mat: &RotMat) {
+        assert_eq!(rot_mat.to_array(), RotMat::IDENTITY.to_array());
+    }
+
+    fn car(id: u64, state: CarState) -> CarInfo {
+        CarInfo {
+            id,
+            state,
+            ..CarInfo::default()
+        }
+    }
+
+    /// A Soccar state with the ball above the ceiling, a car with a NaN position,
+    /// a skewed rotation and too much boost, a demoed car under the map and a car behind a goal
+    fn invalid_game_state() -> GameState {
+        GameState {
+            game_mode: GameMode::Soccar,
+            ball: BallState {
+                pos: Vec3::new(0., 0., 5000.),
+                ..BallState::default()
+            },
+            cars: vec![
+                car(
+                    7,
+                    CarState {
+                        pos: Vec3::new(f32::NAN, 0., 17.),
+                        rot_mat: RotMat::new(Vec3::new(3., 4., 0.), Vec3::new(1., 1., 0.), Vec3::Z),
+                        boost: 150.,
+                        ..CarState::default()
+                    },
+                ),
+                car(
+                    9,
+                    CarState {
+                        pos: Vec3::new(0., 0., -10_000.),
+                        is_demoed: true,
+                        ..CarState::default()
+                    },
+                ),
+                car(
+                    11,
+                    CarState {
+                        pos: Vec3::new(0., 8000., 17.),
+                        ..CarState::default()
+                    },
+                ),
+            ],
+            ..GameState::default()
+        }
+    }
+
+    #[test]
+    fn off_and_warn_leave_the_game_state_alone() {
+        for mode in [ValidationMode::Off, ValidationMode::Warn] {
+            let mut game_state = invalid_game_state();
+            validate(&mut game_state, mode).unwrap();
+
+            assert!(game_state.cars[0].state.pos.x.is_nan());
+            assert_eq!(game_state.cars[0].state.boost, 150.);
+            assert_close(game_state.ball.pos, Vec3::new(0., 0., 5000.));
+        }
+    }
+
+    #[test]
+    fn raise_names_each_problem_with_the_car_index_and_id() {
+        Python::initialize();
+        let mut game_state = invalid_game_state();
+        let e = validate(&mut game_state, ValidationMode::Raise).unwrap_err();
+        let message = Python::attach(|py| e.value(py).to_string());
+
+        let position = |problem: &str| {
+            message
+                .find(problem)
+                .unwrap_or_else(|| panic!("{problem:?} isn't in {message:?}"))
+        };
+        let ball_pos = position("ball pos [0.0, 0.0, 5000.0] is outside of the arena");
+        let car_pos = position("car 0 (id 7) pos [NaN, 0.0, 17.0] isn't finite");
+        let car_rot_mat = position("car 0 (id 7) rot_mat");
+        let car_boost = position("car 0 (id 7) boost 150 isn't between 0 and 100");
+        let other_car_pos =
+            position("car 2 (id 11) pos [0.0, 8000.0, 17.0] is outside of the arena");
+
+        assert!(ball_pos < car_pos && car_pos < car_rot_mat && car_rot_mat < car_boost);
+        assert!(car_boost < other_car_pos);
+        // demoed cars can be anywhere
+        assert!(!message.contains("id 9"), "{message:?}");
+        // raising doesn't repair anything
+        assert!(game_state.cars[0].state.pos.x.is_nan());
+    }
+
+    #[test]
+    fn repair_zeroes_then_orthonormalizes_then_clamps() {
+        let mut game_state = invalid_game_state();
+        validate(&mut game_state, ValidationMode::Repair).unwrap();
+
+        let [car, demoed_car, other_car] = [0, 1, 2].map(|i| game_state.cars[i].state);
+
+        // the NaN position is zeroed, which is then inside the arena
+        assert_eq!(car.pos.to_array(), Vec3::ZERO.to_array());
+        assert!(is_orthonormal(&car.rot_mat));
+        assert_close(car.rot_mat.forward, Vec3::new(0.6, 0.8, 0.));
+        assert_eq!(car.boost, MAX_BOOST);
+
+        assert_close(game_state.ball.pos, Vec3::new(0., 0., 2044.));
+        assert_close(other_car.pos, Vec3::new(0., 6000., 17.));
+        assert_close(demoed_car.pos, Vec3::new(0., 0., -10_000.));
+    }
+
+    #[test]
+    fn the_void_skips_the_bounds_check() {
+        let mut game_state = invalid_game_state();
+        game_state.game_mode = GameMode::TheVoid;
+        validate(&mut game_state, ValidationMode::Repair).unwrap();
+
+        assert_close(game_state.ball.pos, Vec3::new(0., 0., 5000.));
+        assert_close(game_state.cars[2].state.pos, Vec3::new(0., 8000., 17.));
+    }
+
+    #[test]
+    fn identity_is_orthonormal() {
+        assert!(is_orthonormal(&RotMat::IDENTITY));
+        assert!(is_orthonormal(&RotMat::new(
+            Vec3::Y,
+            Vec3::new(-1., 0., 0.),
+            Vec3::Z
+        )));
+    }
+
+    #[test]
+    fn scaled_or_skewed_matrices_arent_orthonormal() {
+        let scaled = RotMat::new(Vec3::X.scale(2.), Vec3::Y, Vec3::Z);
+        let skewed = RotMat::new(Vec3::X, Vec3::new(0.5, 1., 0.), Vec3::Z);
+
+        assert!(!is_orthonormal(&scaled));
+        assert!(!is_orthonormal(&skewed));
+    }
+
+    #[test]
+    fn non_finite_matrices_arent_orthonormal() {
+        let nan = RotMat::new(Vec3::new(f32::NAN, 0., 0.), Vec3::Y, Vec3::Z);
+        let inf = RotMat::new(Vec3::X, Vec3::new(0., f32::INFINITY, 0.), Vec3::Z);
+
+        assert!(!is_orthonormal(&nan));
+        assert!(!is_orthonormal(&inf));
+    }
+
+    #[test]
+    fn orthonormalize_keeps_the_direction_of_forward() {
+        let skewed = RotMat::new(
+            Vec3::new(3., 4., 0.),
+            Vec3::new(1., 1., 0.),
+            Vec3::new(5., 5., 5.),
+        );
+        let fixed = orthonormalize(&skewed);
+
+        assert!(is_orthonormal(&fixed));
+        assert_close(fixed.forward, Vec3::new(0.6, 0.8, 0.));
+        assert_close(fixed.right, Vec3::new(0.8, -0.6, 0.));
+        assert_close(fixed.up, Vec3::Z.scale(-1.));
+    }
+
+    #[test]
+    fn orthonormalize_leaves_orthonormal_matrices_alone() {
+        let rot_mat = RotMat::new(Vec3::Y, Vec3::new(-1., 0., 0.), Vec3::Z);
+        let fixed = orthonormalize(&rot_mat);
+
+        assert_close(fixed.forward, rot_mat.forward);
+        assert_close(fixed.right, rot_mat.right);
+        assert_close(fixed.up, rot_mat.up);
+    }
+
+    #[test]
+    fn orthonormalize_falls_back_to_identity_for_degenerate_matrices() {
+        assert_identity(&orthonormalize(&RotMat::new(Vec3::ZERO, Vec3::Y, Vec3::Z)));
+        // right is parallel to forward, so there's no way to tell which way it should point
+        assert_identity(&orthonormalize(&RotMat::new(
+            Vec3::X,
+            Vec3::X.scale(-2.),
+            Vec3::Z,
+        )));
+        assert_identity(&orthonormalize(&RotMat::new(Vec3::X, Vec3::ZERO, Vec3::Z)));
+    }
+
+    #[test]
+    fn orthonormalize_falls_back_to_identity_for_non_finite_matrices() {
+        assert_identity(&orthonormalize(&RotMat::new(
+            Vec3::new(f32::NAN, 0., 0.),
+            Vec3::Y,
+            Vec3::Z,
+        )));
+        assert_identity(&orthonormalize(&RotMat::new(
+            Vec3::new(f32::INFINITY, 1., 0.),
+            Vec3::Y,
+            Vec3::Z,
+        )));
+        assert_identity(&orthonormalize(&RotMat::new(
+            Vec3::X,
+            Vec3::new(0., f32::NAN, 0.),
+            Vec3::Z,
+        )));
+    }
+
+    #[test]
+    fn the_void_has_no_bounds() {
+        assert!(ArenaBounds::of(GameMode::TheVoid).is_none());
+    }
+
+    #[test]
+    fn clamp_moves_positions_onto_the_arena_bounds() {
+        for game_mode in [
+            GameMode::Soccar,
+            GameMode::Hoops,
+            GameMode::HeatSeeker,
+            GameMode::Snowday,
+            GameMode::Dropshot,
+        ] {
+            let bounds = ArenaBounds::of(game_mode).unwrap();
+            let inside = Vec3::new(bounds.extent_x / 2., -bounds.extent_y / 2., 100.);
+
+            assert_close(bounds.clamp(inside), inside);
+            assert_close(
+                bounds.clamp(Vec3::new(1e6, -1e6, 1e6)),
+                Vec3::new(bounds.extent_x, -bounds.extent_y, bounds.height),
+            );
+            assert_close(
+                bounds.clamp(Vec3::new(-1e6, 1e6, -1e6)),
+                Vec3::new(-bounds.extent_x, bounds.extent_y, 0.),
+            );
+        }
+    }
+
+    #[test]
+    fn contains_allows_some_leeway_past_the_bounds() {
+        let bounds = ArenaBounds::of(GameMode::Soccar).unwrap();
+
+        assert!(bounds.contains(Vec3::new(0., 0., 17.)));
+        assert!(bounds.contains(Vec3::new(4096. + BOUNDS_LEEWAY / 2., 0., 0.)));
+        assert!(bounds.contains(Vec3::new(0., 0., -BOUNDS_LEEWAY / 2.)));
+        assert!(!bounds.contains(Vec3::new(0., 6000. + BOUNDS_LEEWAY * 2., 0.)));
+        assert!(!bounds.contains(Vec3::new(0., 0., 2044. + BOUNDS_LEEWAY * 2.)));
+        assert!(!bounds.contains(Vec3::new(f32::NAN, 0., 0.)));
+    }
+}
@@ -0,0 +1,1101 @@
+use pyo3::{
+    IntoPyObjectExt,
+    exceptions::PyValueError,
+    prelude::*,
+    sync::{MutexExt, PyOnceLock},
+};
+use std::{
+    collections::HashMap,
+    io, mem,
+    path::PathBuf,
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    arrays::{Array1, Array2, Array3, CarArrays},
+    boost_pads::{self, BoostPadConfig, BoostPadLocation},
+    bytes::{
+        BallState, BoostPad, BoostPadState, BoostPadStateArg, CarConfig, CarInfo, CarState,
+        DropshotTile, GameMode, GameState, TBall, TCar, TTile, TTiles, Team, TileState, ToDict,
+        Vec3,
+    },
+    connection::ConnectionTracker,
+    draw::{RenderCommand, RenderGroup, RenderLifetime},
+    error::Result,
+    logging::{self, Level},
+    process::{LaunchConfig, ViewerProcess},
+    socket::{ReturnMessage, SocketConfig, SocketHandler},
+    validate::{self, ValidationMode},
+};
+
+pub type Car = (u64, u8, CarConfig, CarState);
+pub type StateSet = (Vec<f32>, TBall, Vec<TCar>);
+
+/// How long RLViser gets to exit by itself before it's killed,
+/// when `quit(force=True)` has no timeout or when the viewer goes away
+const QUIT_GRACE_PERIOD: Duration = Duration::from_secs(1);
+/// How often `Quit` is sent again while waiting for RLViser to exit, in case it was lost
+const QUIT_RETRY_INTERVAL: Duration = Duration::from_millis(250);
+
+/// A render group that was sent to RLViser, kept so it can be sent again if RLViser restarts
+struct ActiveRenderGroup {
+    group: RenderGroup,
+    added_at: Instant,
+    /// The tick count of the last game state sent since the group was added
+    last_tick: u64,
+    /// Ticks that passed since the group was added, across episode resets
+    elapsed_ticks: u64,
+}
+
+impl ActiveRenderGroup {
+    fn new(group: RenderGroup, tick_count: u64) -> Self {
+        Self {
+            group,
+            added_at: Instant::now(),
+            last_tick: tick_count,
+            elapsed_ticks: 0,
+        }
+    }
+
+    /// Count the ticks up to a newly sent game state,
+    /// where a smaller tick count means the episode was reset and started counting from 0 again
+    fn advance(&mut self, tick_count: u64) {
+        let ticks = tick_count.checked_sub(self.last_tick).unwrap_or(tick_count);
+        self.elapsed_ticks = self.elapsed_ticks.saturating_add(ticks);
+        self.last_tick = tick_count;
+    }
+
+    /// The group with only the part of its lifetime that's left, or `None` if it has expired
+    fn remaining(&self) -> Option<RenderGroup> {
+        let lifetime = match self.group.lifetime {
+            Some(RenderLifetime::Seconds(seconds)) => {
+                let remaining = seconds - self.added_at.elapsed().as_secs_f32();
+                if remaining <= 0. {
+                    return None;
+                }
+
+                Some(RenderLifetime::Seconds(remaining))
+            }
+            Some(RenderLifetime::Ticks(ticks)) => {
+                if self.elapsed_ticks >= ticks {
+                    return None;
+                }
+
+                Some(RenderLifetime::Ticks(ticks - self.elapsed_ticks))
+            }
+            None => None,
+        };
+
+        Some(RenderGroup {
+            lifetime,
+            ..self.group.clone()
+        })
+    }
+}
+
+static DEFAULT_VIEWER: PyOnceLock<Py<Viewer>> = PyOnceLock::new();
+
+/// The viewer used by the module-level functions, created on first use
+pub fn default_viewer(py: Python<'_>) -> PyResult<&Bound<'_, Viewer>> {
+    DEFAULT_VIEWER
+        .get_or_try_init(py, || {
+            Py::new(
+                py,
+                Viewer::new(None, None, None, None, None, None, None, None)?,
+            )
+        })
+        .map(|viewer| viewer.bind(py))
+}
+
+/// Clear the default viewer's renders and close the RLViser it launched, since it's never dropped
+pub fn quit_default_viewer(py: Python) {
+    let Some(viewer) = DEFAULT_VIEWER.get(py) else {
+        return;
+    };
+
+    let state = viewer.get().state(py);
+
+    // RLViser outlives us if it was started some other way or on another machine
+    if let Some(socket_handler) = &state.socket {
+        let _ = socket_handler.clear_renders();
+    }
+
+    let (Some(process), socket) = (state.process.clone(), state.socket.clone()) else {
+        return;
+    };
+
+    // other threads can keep using the viewer while RLViser is closing
+    drop(state);
+    quit_launched(&process, socket.as_deref());
+}
+
+/// Wait for an RLViser we launched to exit, sending `Quit` again every so often,
+/// and kill it if it's still running after `timeout` and `force` is set
+///
+/// Returns whether it's no longer running. Doesn't need the GIL, so call it with it released.
+fn wait_for_quit(
+    process: &ViewerProcess,
+    socket: Option<&SocketHandler>,
+    timeout: Duration,
+    force: bool,
+) -> io::Result<bool> {
+    let start = Instant::now();
+    loop {
+        let remaining = timeout.saturating_sub(start.elapsed());
+        if process
+            .wait(Some(remaining.min(QUIT_RETRY_INTERVAL)))?
+            .is_some()
+        {
+            return Ok(true);
+        }
+
+        if start.elapsed() >= timeout {
+            break;
+        }
+
+        if let Some(socket_handler) = socket
+            && let Err(e) = socket_handler.send_quit()
+        {
+            logging::diagnostic(Level::Debug, "send_error", &[], || e.to_string());
+        }
+    }
+
+    if force {
+        process.kill()?;
+        return Ok(true);
+    }
+
+    Ok(false)
+}
+
+/// Close an RLViser we launched if it's still running,
+/// giving it a moment to exit by itself before killing it
+fn quit_launched(process: &ViewerProcess, socket: Option<&SocketHandler>) {
+    if !process.is_running() {
+        return;
+    }
+
+    if let Some(socket_handler) = socket {
+        let _ = socket_handler.clear_renders();
+        let _ = socket_handler.send_quit();
+    }
+
+    let wait_for_quit = || {
+        if let Err(e) = wait_for_quit(process, socket, QUIT_GRACE_PERIOD, true) {
+            logging::diagnostic(Level::Warning, "quit_error", &[], || {
+                format!("Failed to close RLViser: {e}")
+            });
+        }
+    };
+
+    // don't hold up other Python threads while waiting
+    if Python::try_attach(|py| py.detach(wait_for_quit)).is_none() {
+        wait_for_quit();
+    }
+}
+
+/// Convert the Dropshot tiles given to `render`, checking that their states are valid
+fn tiles_from_arrays(tiles: TTiles) -> PyResult<[Vec<DropshotTile>; 2]> {
+    let [blue_tiles, orange_tiles] = tiles;
+
+    let convert = |team: &str, tiles: Vec<TTile>| {
+        tiles
+            .into_iter()
+            .enumerate()
+            .map(|(i, (pos, state))| {
+                let state = TileState::from_u8(state).ok_or_else(|| {
+                    PyValueError::new_err(format!(
+                        "Invalid state {state} for {team} tile {i}, expected 0 (full), 1 (damaged) or 2 (broken)"
+                    ))
+                })?;
+
+                Ok(DropshotTile {
+                    pos: Vec3::from_array(pos),
+                    state,
+                })
+            })
+            .collect::<PyResult<Vec<_>>>()
+    };
+
+    Ok([
+        convert("blue", blue_tiles)?,
+        convert("orange", orange_tiles)?,
+    ])
+}
+
+/// Convert the cars given to `render`, checking that their teams are valid
+/// and that their ids are non-zero and unique
+fn cars_from_args(cars: Vec<Car>) -> PyResult<Vec<CarInfo>> {
+    let mut indices_by_id = HashMap::with_capacity(cars.len());
+
+    cars.into_iter()
+        .enumerate()
+        .map(|(i, (id, team, config, state))| {
+            if id == 0 {
+                return Err(PyValueError::new_err(format!(
+                    "Invalid id 0 for car {i}, car ids must be non-zero"
+                )));
+            }
+
+            if let Some(other) = indices_by_id.insert(id, i) {
+                return Err(PyValueError::new_err(format!(
+                    "Invalid id {id} for car {i}, car {other} has the same id"
+                )));
+            }
+
+            let team = Team::from_u8(team).ok_or_else(|| {
+                PyValueError::new_err(format!(
+                    "Invalid team {team} for car {i} (id {id}), expected 0 (blue) or 1 (orange)"
+                ))
+            })?;
+
+            Ok(CarInfo {
+                id,
+                team,
+                config,
+                state,
+            })
+        })
+        .collect()
+}
+
+/// Parse a timeout in seconds from Python
+fn parse_timeout(timeout: f64) -> PyResult<Duration> {
+    Duration::try_from_secs_f64(timeout)
+        .map_err(|e| PyValueError::new_err(format!("Invalid timeout: {e}")))
+}
+
+/// A connection to a single instance of RLViser
+///
+/// Each viewer has its own socket, boost pad locations and game speed/pause/state set requests.
+/// The module-level functions all use a default viewer that's shared by the whole process.
+///
+/// Any address or port that isn't given is read from the `RLVISER_BIND_ADDR`, `RLVISER_LOCAL_PORT`,
+/// `RLVISER_HOST` and `RLVISER_PORT` environment variables, falling back to `0.0.0.0:34254` locally
+/// and RLViser at `127.0.0.1:45243`. A `local_port` of 0 lets the OS pick a free port.
+#[pyclass(module = "rlviser_py", frozen)]
+pub struct Viewer {
+    /// Threads using the viewer at the same time wait their turn instead of raising,
+    /// see `Viewer::state`
+    state: Mutex<ViewerState>,
+}
+
+/// Everything a `Viewer` keeps track of
+struct ViewerState {
+    config: SocketConfig,
+    launch_config: LaunchConfig,
+    // shared so RLViser can be waited on without keeping the viewer locked
+    socket: Option<Arc<SocketHandler>>,
+    process: Option<Arc<ViewerProcess>>,
+    /// Whether RLViser was launched or checked for when first rendering,
+    /// which `is_connected()` connecting first doesn't count as
+    launch_attempted: bool,
+    /// `None` uses the standard layout of the game mode
+    boost_pads: Option<Vec<BoostPadConfig>>,
+    game_state: Option<GameState>,
+    game_speed: f32,
+    game_paused: bool,
+    last_sent_state: Option<GameState>,
+    render_groups: HashMap<i32, ActiveRenderGroup>,
+    connection: ConnectionTracker,
+    on_connection_change: Option<Py<PyAny>>,
+    /// Connection changes that `on_connection_change` hasn't been called for yet
+    connection_changes: Vec<bool>,
+    validation: ValidationMode,
+}
+
+impl ViewerState {
+    /// Get the socket, connecting to RLViser on first use
+    /// and launching it too if it's meant to run on this machine
+    fn socket(&mut self) -> Result<&SocketHandler> {
+        // RLViser on another machine has to be started there, or with `launch()` to run it here anyway,
+        // and it might've been started some other way, so failing to launch it isn't an error
+        if !mem::replace(&mut self.launch_attempted, true)
+            && self.config.rlviser_is_local()
+            && !self.config.rlviser_is_listening()
+            && let Err(e) = self.spawn_process()
+        {
+            logging::diagnostic(Level::Warning, "launch_error", &[], || e.to_string());
+        }
+
+        self.connect()
+    }
+
+    /// Get the socket, connecting to RLViser on first use without launching it
+    fn connect(&mut self) -> Result<&SocketHandler> {
+        let socket_handler = match self.socket.take() {
+            Some(socket_handler) => socket_handler,
+            None => {
+                let socket_handler = Arc::new(SocketHandler::new(&self.config)?);
+                // the handshake was just sent
+                self.connection.heartbeat_sent();
+                socket_handler
+            }
+        };
+
+        Ok(self.socket.insert(socket_handler))
+    }
+
+    /// Launch RLViser, unless the one we launched before is still running
+    fn spawn_process(&mut self) -> Result<()> {
+        if self.is_rlviser_running() {
+            return Ok(());
+        }
+
+        self.process = Some(Arc::new(ViewerProcess::spawn(&self.launch_config)?));
+        Ok(())
+    }
+
+    fn is_rlviser_running(&self) -> bool {
+        self.process
+            .as_deref()
+            .is_some_and(ViewerProcess::is_running)
+    }
+
+    /// The tick count of the last game state that was sent
+    fn tick_count(&self) -> u64 {
+        self.last_sent_state
+            .as_ref()
+            .map_or(0, |game_state| game_state.tick_count)
+    }
+
+    /// Count the ticks of each render group up to the last game state sent
+    /// and forget the ones that have expired, so adding a new group each frame doesn't pile them up
+    fn prune_render_groups(&mut self) {
+        let tick_count = self.tick_count();
+        self.render_groups.retain(|_, active| {
+            active.advance(tick_count);
+            active.remaining().is_some()
+        });
+    }
+
+    /// Send everything a freshly started RLViser is missing,
+    /// the render groups that are still active and the last game state with the boost pad layout
+    fn resend_state(&mut self) -> Result<()> {
+        self.prune_render_groups();
+
+        let Some(socket_handler) = &self.socket else {
+            return Ok(());
+        };
+
+        for active in self.render_groups.values() {
+            if let Some(group) = active.remaining() {
+                socket_handler.add_render(&group)?;
+            }
+        }
+
+        if let Some(game_state) = &self.last_sent_state {
+            socket_handler.send_game_state(game_state)?;
+        }
+
+        Ok(())
+    }
+
+    /// Store anything RLViser sent since the last call and keep the heartbeat going
+    fn update_return_state(&mut self) {
+        let Some(socket_handler) = &self.socket else {
+            return;
+        };
+
+        let return_message = socket_handler.handle_return_messages().unwrap_or_else(|e| {
+            logging::diagnostic(Level::Warning, "receive_error", &[], || {
+                format!("Failed to receive messages from RLViser: {e}")
+            });
+            ReturnMessage::NONE
+        });
+
+        if return_message.heard_from_rlviser {
+            self.connection.seen();
+        }
+
+        if self.connection.heartbeat_due() {
+            // keep trying the handshake until RLViser answers,
+            // a message that fails to send just means we won't hear back
+            let sent = if self.connection.is_connected() {
+                socket_handler.send_heartbeat()
+            } else {
+                socket_handler.send_connection()
+            };
+
+            if let Err(e) = sent {
+                logging::diagnostic(Level::Debug, "send_error", &[], || e.to_string());
+            }
+
+            self.connection.heartbeat_sent();
+        }
+
+        let connection_change = self.connection.update();
+
+        // RLViser either restarted or it's the first time we've heard from it,
+        // if resending fails here then it'll be tried again on the next handshake
+        if (return_message.handshake || connection_change == Some(true))
+            && let Err(e) = self.resend_state()
+        {
+            logging::diagnostic(Level::Warning, "send_error", &[], || e.to_string());
+        }
+
+        if connection_change == Some(false) {
+            logging::diagnostic(Level::Info, "connection", &[], || {
+                "Lost the connection to RLViser".to_string()
+            });
+        }
+
+        if let Some(connected) = connection_change {
+            self.connection_changes.push(connected);
+        }
+
+        if let Some(speed) = return_message.speed {
+            self.game_speed = speed;
+        }
+
+        if let Some(paused) = return_message.paused {
+            self.game_paused = paused;
+        }
+
+        if let Some(game_state) = return_message.game_state {
+            self.game_state = Some(game_state);
+        }
+    }
+
+    /// Send a game state to RLViser, see `render`
+    #[allow(clippy::too_many_arguments)]
+    fn send_render(
+        &mut self,
+        tick_count: u64,
+        tick_rate: f32,
+        game_mode: u8,
+        boost_pad_states: Vec<BoostPadStateArg>,
+        ball: BallState,
+        cars: Vec<Car>,
+        tiles: Option<TTiles>,
+        validation: Option<&str>,
+    ) -> PyResult<()> {
+        let validation = validation
+            .map(ValidationMode::parse)
+            .transpose()?
+            .unwrap_or(self.validation);
+        let game_mode = GameMode::from_u8(game_mode).ok_or_else(|| {
+            PyValueError::new_err(format!(
+                "Invalid game mode {game_mode}, expected 0 (soccar) through 5 (the void)"
+            ))
+        })?;
+        let boost_pads = self
+            .boost_pads
+            .as_deref()
+            .unwrap_or_else(|| boost_pads::default_layout(game_mode));
+
+        // without any locations there are no pads to send, so the states don't matter
+        if !boost_pads.is_empty() && boost_pad_states.len() != boost_pads.len() {
+            return Err(PyValueError::new_err(format!(
+                "Invalid length {} for boost_pad_states, expected {} for the boost pad locations",
+                boost_pad_states.len(),
+                boost_pads.len()
+            )));
+        }
+
+        let mut game_state = GameState {
+            tick_count,
+            tick_rate,
+            game_mode,
+            ball,
+            pads: boost_pads
+                .iter()
+                .zip(boost_pad_states)
+                .map(|(config, state)| BoostPad {
+                    position: config.position,
+                    is_big: config.is_big,
+                    state: BoostPadState::from(state),
+                })
+                .collect(),
+            cars: cars_from_args(cars)?,
+            tiles: tiles.map(tiles_from_arrays).transpose()?,
+        };
+        validate::validate(&mut game_state, validation)?;
+
+        self.socket()?.send_game_state(&game_state)?;
+        self.last_sent_state = Some(game_state);
+        self.prune_render_groups();
+        self.update_return_state();
+        Ok(())
+    }
+}
+
+impl Viewer {
+    /// Lock the viewer's state
+    ///
+    /// Waiting for the lock releases the GIL, so the thread holding it can always finish
+    /// even if it runs Python code (like logging) in the meantime
+    fn state(&self, py: Python) -> MutexGuard<'_, ViewerState> {
+        self.state
+            .lock_py_attached(py)
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Unlock the viewer, then call `on_connection_change` for each change since the last call
+    ///
+    /// The callback is only called once the viewer is unlocked so it can use the viewer too,
+    /// which is why the methods that can change the connection hand their lock over to this
+    fn notify_connection_changes(py: Python, mut state: MutexGuard<'_, ViewerState>) {
+        let changes = mem::take(&mut state.connection_changes);
+        let callback = state
+            .on_connection_change
+            .as_ref()
+            .map(|callback| callback.clone_ref(py));
+        drop(state);
+
+        let Some(callback) = callback else {
+            return;
+        };
+
+        for connected in changes {
+            if let Err(e) = callback.call1(py, (connected,)) {
+                e.write_unraisable(py, Some(callback.bind(py)));
+            }
+        }
+    }
+}
+
+#[pymethods]
+impl Viewer {
+    #[new]
+    #[allow(clippy::too_many_arguments)]
+    #[pyo3(signature = (bind_addr=None, local_port=None, rlviser_host=None, rlviser_port=None, rlviser_path=None, rlviser_args=None, rlviser_env=None, rlviser_log_file=None))]
+    pub fn new(
+        bind_addr: Option<&str>,
+        local_port: Option<u16>,
+        rlviser_host: Option<String>,
+        rlviser_port: Option<u16>,
+        rlviser_path: Option<PathBuf>,
+        rlviser_args: Option<Vec<String>>,
+        rlviser_env: Option<HashMap<String, String>>,
+        rlviser_log_file: Option<PathBuf>,
+    ) -> PyResult<Self> {
+        let config = SocketConfig::from_env()?.with_overrides(
+            bind_addr,
+            local_port,
+            rlviser_host,
+            rlviser_port,
+        )?;
+        let launch_config = LaunchConfig::from_env().with_overrides(
+            rlviser_path,
+            rlviser_args,
+            rlviser_env,
+            rlviser_log_file,
+        );
+
+        Ok(Self {
+            state: Mutex::new(ViewerState {
+                config,
+                launch_config,
+                socket: None,
+                process: None,
+                launch_attempted: false,
+                boost_pads: None,
+                game_state: None,
+                game_speed: 1.0,
+                game_paused: false,
+                last_sent_state: None,
+                render_groups: HashMap::new(),
+                connection: ConnectionTracker::default(),
+                on_connection_change: None,
+                connection_changes: Vec::new(),
+                validation: ValidationMode::Off,
+            }),
+        })
+    }
+
+    /// Set the boost pad locations to send to RLViser in each packet,
+    /// or `None` to go back to the standard layout of the game mode being rendered
+    pub fn set_boost_pad_locations(&self, py: Python, locations: Option<Vec<BoostPadLocation>>) {
+        self.state(py).boost_pads =
+            locations.map(|locations| locations.into_iter().map(BoostPadConfig::from).collect());
+    }
+
+    /// Set what `render` does with game states that have non-finite values,
+    /// non-orthonormal rotations, objects outside of the arena or boost outside of 0 to 100:
+    /// `"off"` (the default), `"warn"`, `"raise"` or `"repair"`
+    pub fn set_validation(&self, py: Python, mode: &str) -> PyResult<()> {
+        self.state(py).validation = ValidationMode::parse(mode)?;
+        Ok(())
+    }
+
+    /// The state that RLViser wants to set, if it has sent one since the last call
+    ///
+    /// With `include_tiles`, the Dropshot tiles (or `None` outside of Dropshot) are added as a fourth item.
+    /// With `full`, the whole game state is returned as a dict with every field instead
+    #[pyo3(signature = (include_tiles=false, full=false))]
+    pub fn get_state_set<'py>(
+        &self,
+        py: Python<'py>,
+        include_tiles: bool,
+        full: bool,
+    ) -> PyResult<Option<Bound<'py, PyAny>>> {
+        let mut state = self.state(py);
+        state.update_return_state();
+        let game_state = state.game_state.take();
+        Self::notify_connection_changes(py, state);
+
+        let Some(game_state) = game_state else {
+            return Ok(None);
+        };
+
+        if full {
+            return game_state.to_dict(py).map(|dict| Some(dict.into_any()));
+        }
+
+        let pads = game_state
+            .pads
+            .into_iter()
+            .map(|pad| pad.state.cooldown)
+            .collect::<Vec<_>>();
+        let cars = game_state
+            .cars
+            .into_iter()
+            .map(CarInfo::to_array)
+            .collect::<Vec<_>>();
+
+        let state_set: StateSet = (pads, game_state.ball.to_array(), cars);
+
+        if !include_tiles {
+            return state_set.into_bound_py_any(py).map(Some);
+        }
+
+        // a tuple like the `TTiles` in the stub, since arrays become lists
+        let tiles = game_state.tiles.map(|tiles| {
+            let [blue_tiles, orange_tiles]: TTiles =
+                tiles.map(|tiles| tiles.into_iter().map(DropshotTile::to_array).collect());
+            (blue_tiles, orange_tiles)
+        });
+        let (pads, ball, cars) = state_set;
+
+        (pads, ball, cars, tiles).into_bound_py_any(py).map(Some)
+    }
+
+    pub fn get_game_speed(&self, py: Python) -> f32 {
+        let mut state = self.state(py);
+        state.update_return_state();
+        let game_speed = state.game_speed;
+        Self::notify_connection_changes(py, state);
+        game_speed
+    }
+
+    pub fn get_game_paused(&self, py: Python) -> bool {
+        let mut state = self.state(py);
+        state.update_return_state();
+        let game_paused = state.game_paused;
+        Self::notify_connection_changes(py, state);
+        game_paused
+    }
+
+    /// Whether RLViser has responded in the last few seconds
+    ///
+    /// The first call connects to RLViser without launching it, so it answers in time for later ones
+    pub fn is_connected(&self, py: Python) -> PyResult<bool> {
+        let mut state = self.state(py);
+        state.connect()?;
+        state.update_return_state();
+        let connected = state.connection.is_connected();
+        Self::notify_connection_changes(py, state);
+        Ok(connected)
+    }
+
+    /// Seconds since anything was received from RLViser, or `None` if nothing has been
+    ///
+    /// The first call connects to RLViser without launching it, so it answers in time for later ones
+    pub fn last_seen_seconds(&self, py: Python) -> PyResult<Option<f64>> {
+        let mut state = self.state(py);
+        state.connect()?;
+        state.update_return_state();
+        let last_seen = state.connection.last_seen();
+        Self::notify_connection_changes(py, state);
+        Ok(last_seen.map(|last_seen| last_seen.as_secs_f64()))
+    }
+
+    /// Set a function that's called with `True` when RLViser connects and `False` when it's lost,
+    /// or `None` to remove it
+    pub fn on_connection_change(&self, py: Python, callback: Option<Py<PyAny>>) {
+        let previous = mem::replace(&mut self.state(py).on_connection_change, callback);
+        // dropping the old callback can run Python code, so only once the viewer is unlocked
+        drop(previous);
+    }
+
+    pub fn report_game_speed(&self, py: Python, speed: f32) -> PyResult<()> {
+        self.state(py).socket()?.report_game_speed(speed)?;
+        Ok(())
+    }
+
+    pub fn report_game_paused(&self, py: Python, paused: bool) -> PyResult<()> {
+        self.state(py).socket()?.report_game_paused(paused)?;
+        Ok(())
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    #[pyo3(signature = (tick_count, tick_rate, game_mode, boost_pad_states, ball, cars, tiles=None, validation=None))]
+    pub fn render(
+        &self,
+        py: Python,
+        tick_count: u64,
+        tick_rate: f32,
+        game_mode: u8,
+        boost_pad_states: Vec<BoostPadStateArg>,
+        ball: BallState,
+        cars: Vec<Car>,
+        tiles: Option<TTiles>,
+        validation: Option<&str>,
+    ) -> PyResult<()> {
+        let mut state = self.state(py);
+        state.send_render(
+            tick_count,
+            tick_rate,
+            game_mode,
+            boost_pad_states,
+            ball,
+            cars,
+            tiles,
+            validation,
+        )?;
+        Self::notify_connection_changes(py, state);
+        Ok(())
+    }
+
+    /// Like `render`, but with the cars given as NumPy arrays with one row per car
+    /// instead of reading every attribute of a `CarState`
+    ///
+    /// Cars without a config reuse the one they were last rendered with
+    #[allow(clippy::too_many_arguments)]
+    #[pyo3(signature = (tick_count, tick_rate, game_mode, boost_pad_states, ball, car_ids, car_teams, car_positions, car_rotations, car_velocities, car_ang_velocities, car_boost, car_flags, car_configs=None, tiles=None, validation=None))]
+    pub fn render_arrays(
+        &self,
+        py: Python,
+        tick_count: u64,
+        tick_rate: f32,
+        game_mode: u8,
+        boost_pad_states: Vec<BoostPadStateArg>,
+        ball: BallState,
+        car_ids: Array1<u64>,
+        car_teams: Array1<u8>,
+        car_positions: Array2<f32>,
+        car_rotations: Array3<f32>,
+        car_velocities: Array2<f32>,
+        car_ang_velocities: Array2<f32>,
+        car_boost: Array1<f32>,
+        car_flags: Array2<bool>,
+        car_configs: Option<Vec<CarConfig>>,
+        tiles: Option<TTiles>,
+        validation: Option<&str>,
+    ) -> PyResult<()> {
+        let mut state = self.state(py);
+        let cars = CarArrays {
+            ids: car_ids,
+            teams: car_teams,
+            positions: car_positions,
+            rotations: car_rotations,
+            velocities: car_velocities,
+            ang_velocities: car_ang_velocities,
+            boost: car_boost,
+            flags: car_flags,
+        }
+        .into_cars(car_configs, state.last_sent_state.as_ref())?;
+
+        state.send_render(
+            tick_count,
+            tick_rate,
+            game_mode,
+            boost_pad_states,
+            ball,
+            cars,
+            tiles,
+            validation,
+        )?;
+        Self::notify_connection_changes(py, state);
+        Ok(())
+    }
+
+    /// Render the current state of a RocketSim `Arena`
+    ///
+    /// If no boost pad locations have been set, they're set from the arena's pads on first use
+    #[pyo3(signature = (arena, tiles=None, validation=None))]
+    pub fn render_arena(
+        &self,
+        arena: &Bound<PyAny>,
+        tiles: Option<TTiles>,
+        validation: Option<&str>,
+    ) -> PyResult<()> {
+        let py = arena.py();
+        // everything is read from the arena before locking the viewer,
+        // since RocketSim's getters are Python calls that other threads can run during
+        let pads = arena
+            .call_method0("get_boost_pads")?
+            .try_iter()?
+            .collect::<PyResult<Vec<_>>>()?;
+
+        let locations = if self.state(py).boost_pads.is_none() {
+            let locations = pads
+                .iter()
+                .map(|pad| {
+                    Ok(BoostPadConfig {
+                        position: pad.call_method0("get_pos")?.extract()?,
+                        is_big: pad.getattr("is_big")?.extract()?,
+                    })
+                })
+                .collect::<PyResult<_>>()?;
+            Some(locations)
+        } else {
+            None
+        };
+
+        let boost_pad_states = pads
+            .iter()
+            .map(|pad| pad.call_method0("get_state")?.extract())
+            .collect::<PyResult<_>>()?;
+
+        let cars = arena
+            .call_method0("get_cars")?
+            .try_iter()?
+            .map(|car| {
+                let car = car?;
+                Ok((
+                    car.getattr("id")?.extract()?,
+                    car.getattr("team")?.extract()?,
+                    car.call_method0("get_config")?.extract()?,
+                    car.call_method0("get_state")?.extract()?,
+                ))
+            })
+            .collect::<PyResult<_>>()?;
+
+        let tick_count = arena.getattr("tick_count")?.extract()?;
+        let tick_rate = arena.getattr("tick_rate")?.extract()?;
+        let game_mode = arena.getattr("game_mode")?.extract()?;
+        let ball = arena
+            .getattr("ball")?
+            .call_method0("get_state")?
+            .extract()?;
+
+        let mut state = self.state(py);
+        if state.boost_pads.is_none() {
+            state.boost_pads = locations;
+        }
+
+        state.send_render(
+            tick_count,
+            tick_rate,
+            game_mode,
+            boost_pad_states,
+            ball,
+            cars,
+            tiles,
+            validation,
+        )?;
+        Self::notify_connection_changes(py, state);
+        Ok(())
+    }
+
+    /// Add a group of debug render commands to RLViser, replacing any existing group with the same id
+    #[pyo3(signature = (group_id, commands, lifetime_seconds=None, lifetime_ticks=None))]
+    pub fn add_render(
+        &self,
+        py: Python,
+        group_id: i32,
+        commands: Vec<RenderCommand>,
+        lifetime_seconds: Option<f32>,
+        lifetime_ticks: Option<u64>,
+    ) -> PyResult<()> {
+        let lifetime = match (lifetime_seconds, lifetime_ticks) {
+            (Some(seconds), None) if seconds.is_finite() && seconds > 0. => {
+                Some(RenderLifetime::Seconds(seconds))
+            }
+            (Some(seconds), None) => {
+                return Err(PyValueError::new_err(format!(
+                    "lifetime_seconds must be finite and positive, not {seconds}"
+                )));
+            }
+            (None, Some(ticks)) => Some(RenderLifetime::Ticks(ticks)),
+            (None, None) => None,
+            (Some(_), Some(_)) => {
+                return Err(PyValueError::new_err(
+                    "only one of lifetime_seconds and lifetime_ticks can be set",
+                ));
+            }
+        };
+
+        let group = RenderGroup {
+            id: group_id,
+            commands: commands.into_iter().map(|command| command.0).collect(),
+            lifetime,
+        };
+
+        let mut state = self.state(py);
+        state.socket()?.add_render(&group)?;
+
+        state.prune_render_groups();
+        let active = ActiveRenderGroup::new(group, state.tick_count());
+        state.render_groups.insert(group_id, active);
+
+        Ok(())
+    }
+
+    /// Remove a group of debug render commands from RLViser
+    pub fn remove_render(&self, py: Python, group_id: i32) -> PyResult<()> {
+        let mut state = self.state(py);
+        state.render_groups.remove(&group_id);
+
+        if let Some(socket_handler) = &state.socket {
+            socket_handler.remove_render(group_id)?;
+        }
+
+        Ok(())
+    }
+
+    /// Remove every group of debug render commands this viewer added to RLViser
+    pub fn clear_renders(&self, py: Python) -> PyResult<()> {
+        let mut state = self.state(py);
+        state.render_groups.clear();
+
+        if let Some(socket_handler) = &state.socket {
+            socket_handler.clear_renders()?;
+        }
+
+        Ok(())
+    }
+
+    /// Open RLViser, optionally changing where this viewer connects from and to
+    /// and which executable is started with what arguments and environment variables
+    #[pyo3(signature = (bind_addr=None, local_port=None, rlviser_host=None, rlviser_port=None, rlviser_path=None, rlviser_args=None, rlviser_env=None, rlviser_log_file=None))]
+    #[allow(clippy::too_many_arguments)]
+    pub fn launch(
+        &self,
+        py: Python,
+        bind_addr: Option<&str>,
+        local_port: Option<u16>,
+        rlviser_host: Option<String>,
+        rlviser_port: Option<u16>,
+        rlviser_path: Option<PathBuf>,
+        rlviser_args: Option<Vec<String>>,
+        rlviser_env: Option<HashMap<String, String>>,
+        rlviser_log_file: Option<PathBuf>,
+    ) -> PyResult<()> {
+        let mut state = self.state(py);
+        state.launch_config = state.launch_config.clone().with_overrides(
+            rlviser_path,
+            rlviser_args,
+            rlviser_env,
+            rlviser_log_file,
+        );
+
+        let config = state.config.clone().with_overrides(
+            bind_addr,
+            local_port,
+            rlviser_host,
+            rlviser_port,
+        )?;
+
+        if config != state.config {
+            // reconnect with the new config on next use
+            state.socket = None;
+            state.config = config;
+        }
+
+        state.spawn_process()?;
+        state.launch_attempted = true;
+
+        // the new RLViser needs the handshake before it knows about us,
+        // everything else gets sent again once it answers
+        if let Some(connected) = state.connection.reset() {
+            state.connection_changes.push(connected);
+        }
+
+        if let Some(socket_handler) = &state.socket {
+            socket_handler.send_connection()?;
+            state.connection.heartbeat_sent();
+        }
+
+        Self::notify_connection_changes(py, state);
+        Ok(())
+    }
+
+    /// Whether the RLViser launched by this viewer is still running
+    pub fn is_rlviser_running(&self, py: Python) -> bool {
+        self.state(py).is_rlviser_running()
+    }
+
+    /// The process id of the RLViser launched by this viewer, or `None` if it hasn't been launched
+    pub fn rlviser_pid(&self, py: Python) -> Option<u32> {
+        self.state(py).process.as_deref().map(ViewerProcess::id)
+    }
+
+    /// The exit code of the RLViser launched by this viewer,
+    /// or `None` if it's still running, hasn't been launched or was killed by a signal
+    pub fn rlviser_exit_code(&self, py: Python) -> PyResult<Option<i32>> {
+        let Some(process) = self.state(py).process.clone() else {
+            return Ok(None);
+        };
+
+        Ok(process.exit_status()?.and_then(|status| status.code()))
+    }
+
+    /// Wait for the RLViser launched by this viewer to exit and return its exit code,
+    /// or `None` if `timeout` seconds pass first
+    #[pyo3(signature = (timeout=None))]
+    pub fn wait_for_rlviser(&self, py: Python, timeout: Option<f64>) -> PyResult<Option<i32>> {
+        let timeout = timeout.map(parse_timeout).transpose()?;
+
+        // cloned so other threads can keep rendering while this one waits
+        let Some(process) = self.state(py).process.clone() else {
+            return Ok(None);
+        };
+
+        let exit_status = py.detach(|| process.wait(timeout))?;
+        Ok(exit_status.and_then(|status| status.code()))
+    }
+
+    /// Send the quit signal to RLViser
+    ///
+    /// With a `timeout`, wait up to that many seconds for the RLViser launched by this viewer to exit,
+    /// sending the quit signal again in case it was lost.
+    /// With `force`, kill it if it's still running after the timeout, which defaults to a second.
+    /// Returns whether the RLViser launched by this viewer (if any) is no longer running.
+    ///
+    /// Render groups that were added are kept and sent again if RLViser is launched again
+    #[pyo3(signature = (timeout=None, force=false))]
+    pub fn quit(&self, py: Python, timeout: Option<f64>, force: bool) -> PyResult<bool> {
+        let timeout = timeout.map(parse_timeout).transpose()?;
+
+        let mut state = self.state(py);
+        if let Some(socket_handler) = &state.socket {
+            socket_handler.clear_renders()?;
+            socket_handler.send_quit()?;
+        }
+
+        if let Some(connected) = state.connection.reset() {
+            state.connection_changes.push(connected);
+        }
+
+        let (process, socket) = (state.process.clone(), state.socket.clone());
+        // unlocks the viewer, so other threads can keep using it while this one waits
+        Self::notify_connection_changes(py, state);
+
+        let Some(process) = process else {
+            return Ok(true);
+        };
+
+        let exited = match timeout.or(force.then_some(QUIT_GRACE_PERIOD)) {
+            Some(timeout) => {
+                py.detach(|| wait_for_quit(&process, socket.as_deref(), timeout, force))?
+            }
+            None => !process.is_running(),
+        };
+
+        Ok(exited)
+    }
+}
+
+impl Drop for Viewer {
+    fn drop(&mut self) {
+        let state = self.state.get_mut().unwrap_or_else(PoisonError::into_inner);
+
+        if let Some(process) = &state.process {
+            quit_launched(process, state.socket.as_deref());
+        }
+    }
+}